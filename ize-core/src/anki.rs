@@ -0,0 +1,409 @@
+//! Import of Anki `.apkg` and `.colpkg` packages.
+//!
+//! A package is a zip archive holding the SQLite collection. Each note becomes one card whose id
+//! is the Anki note id, so re-importing an updated package keeps run progress.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rusqlite::{Connection, OpenFlags};
+use zip::ZipArchive;
+
+use crate::{
+    card_ids::ReadCard,
+    error::IzeError,
+    item::{deck_item_id, item_id, ItemKind},
+    scheduler::{CardSchedule, SECONDS_PER_DAY},
+    tags::parse_tags,
+    Card, Deck, PracticeRun,
+};
+
+const PACKAGE_EXTENSIONS: [&str; 2] = ["apkg", "colpkg"];
+
+/// Collections in the order they are preferred. Newer Anki versions also write a legacy
+/// `collection.anki2` which only holds a note asking the user to upgrade.
+const COLLECTION_NAMES: [(&str, bool); 3] = [
+    ("collection.anki21b", true),
+    ("collection.anki21", false),
+    ("collection.anki2", false),
+];
+
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+// Values of the `type` column of the cards table.
+const CARD_TYPE_LEARNING: i64 = 1;
+const CARD_TYPE_REVIEW: i64 = 2;
+const CARD_TYPE_RELEARNING: i64 = 3;
+
+// Anki stores ease as a permille factor.
+const MIN_EASE: f64 = 1.3;
+const MAX_EASE: f64 = 3.0;
+
+pub fn is_anki_package(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            PACKAGE_EXTENSIONS
+                .iter()
+                .any(|package| extension.eq_ignore_ascii_case(package))
+        })
+}
+
+/// The collection extracted to a temporary file, removed again when dropped.
+struct Collection {
+    connection: Option<Connection>,
+    path: PathBuf,
+}
+
+impl Collection {
+    fn connection(&self) -> &Connection {
+        self.connection.as_ref().expect("Collection already closed")
+    }
+}
+
+impl Drop for Collection {
+    fn drop(&mut self) {
+        // Close the database before removing its file.
+        self.connection.take();
+        _ = fs::remove_file(&self.path);
+    }
+}
+
+fn temp_collection_path() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("ize-anki-{}-{}.sqlite", process::id(), count))
+}
+
+fn open_collection(package_path: &str) -> Result<Collection, IzeError> {
+    let file = File::open(package_path).map_err(|e| IzeError::io(package_path, e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| IzeError::format(package_path, e))?;
+
+    let (name, compressed) = COLLECTION_NAMES
+        .iter()
+        .find(|(name, _)| archive.by_name(name).is_ok())
+        .ok_or_else(|| IzeError::format(package_path, "No collection found in the package."))?;
+
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| IzeError::format(package_path, e))?;
+
+    let path = temp_collection_path();
+    let mut collection = Collection {
+        connection: None,
+        path: path.clone(),
+    };
+
+    let mut output = File::create(&path).map_err(|e| IzeError::io(&path.to_string_lossy(), e))?;
+    let copied = if *compressed {
+        zstd::stream::copy_decode(&mut entry, &mut output)
+    } else {
+        io::copy(&mut entry, &mut output).map(|_| ())
+    };
+    copied.map_err(|e| IzeError::format(package_path, e))?;
+    drop(output);
+
+    let connection = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| IzeError::format(package_path, e))?;
+    collection.connection = Some(connection);
+
+    Ok(collection)
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "nbsp" => Some(' '),
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let number = entity.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+fn is_line_break(tag: &str) -> bool {
+    let closing = tag.starts_with('/');
+    let name: String = tag
+        .trim_start_matches('/')
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+
+    name == "br" || (closing && matches!(name.as_str(), "div" | "p" | "li" | "tr"))
+}
+
+/// Convert a field's HTML to plain text, keeping line breaks and dropping media references.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let tag = &rest[1..end];
+                if is_line_break(tag) {
+                    text.push('\n');
+                }
+                rest = &rest[end + 1..];
+                continue;
+            }
+        } else if c == '&' {
+            let entity = rest[1..]
+                .find(';')
+                .and_then(|end| Some((end, decode_entity(&rest[1..end + 1])?)));
+            if let Some((end, decoded)) = entity {
+                text.push(decoded);
+                rest = &rest[end + 2..];
+                continue;
+            }
+        } else if rest.starts_with("[sound:") {
+            if let Some(end) = rest.find(']') {
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    let lines: Vec<&str> = text.lines().map(|line| line.trim_end()).collect();
+    lines.join("\n").trim().to_string()
+}
+
+fn format_error(package_path: &str) -> impl Fn(rusqlite::Error) -> IzeError + '_ {
+    move |e| IzeError::format(package_path, e)
+}
+
+/// Read every note in the package as a card.
+pub fn import_deck(package_path: &str) -> Result<Deck, IzeError> {
+    let collection = open_collection(package_path)?;
+    let error = format_error(package_path);
+
+    let mut statement = collection
+        .connection()
+        .prepare("SELECT id, flds, tags FROM notes ORDER BY id")
+        .map_err(&error)?;
+    let notes = statement
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(&error)?;
+
+    let mut deck = Deck::new();
+    deck.title = Path::new(package_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string());
+
+    let mut cards = vec![];
+    for note in notes {
+        let (note_id, fields, tags) = note.map_err(&error)?;
+        let mut fields = fields.split(FIELD_SEPARATOR).map(html_to_text);
+
+        cards.push(ReadCard {
+            card_id: Some(note_id as usize),
+            line: 0,
+            card: Card {
+                card_id: 0,
+                front: fields.next().unwrap_or_default(),
+                back: fields.next().unwrap_or_default(),
+                direction: None,
+                tags: parse_tags(&tags),
+            },
+        });
+    }
+    deck.add_read_cards(cards)
+        .map_err(|e| e.into_error(package_path))?;
+
+    Ok(deck)
+}
+
+/// Review state of one card of a note.
+struct AnkiCard {
+    note_id: usize,
+    /// Which card of the note this is. For cloze notes, the cloze number less one.
+    ordinal: usize,
+    card_type: i64,
+    due: i64,
+    interval: i64,
+    factor: i64,
+    repetitions: i64,
+    lapses: i64,
+    last_review: Option<i64>,
+}
+
+impl AnkiCard {
+    fn is_learning(&self) -> bool {
+        self.card_type == CARD_TYPE_LEARNING || self.card_type == CARD_TYPE_RELEARNING
+    }
+
+    fn schedule(&self, creation_day: u64) -> CardSchedule {
+        let interval = self.interval.max(1) as u64;
+        let ease = if self.factor > 0 {
+            (self.factor as f64 / 1000.0).clamp(MIN_EASE, MAX_EASE)
+        } else {
+            CardSchedule::default().ease
+        };
+
+        // Review cards are due a number of days after the collection was created, learning cards
+        // at a unix timestamp.
+        let due = if self.card_type == CARD_TYPE_REVIEW {
+            creation_day + self.due.max(0) as u64
+        } else {
+            self.due.max(0) as u64 / SECONDS_PER_DAY
+        };
+
+        let last_review = self
+            .last_review
+            .map(|millis| millis.max(0) as u64 / 1000 / SECONDS_PER_DAY)
+            .unwrap_or_else(|| due.saturating_sub(interval));
+
+        CardSchedule {
+            ease,
+            interval,
+            repetitions: self.repetitions.max(0) as u32,
+            lapses: self.lapses.max(0) as u32,
+            due,
+            // Anki intervals target 90% recall, which is what stability measures.
+            stability: interval as f64,
+            // Harder cards have a lower ease.
+            difficulty: 10.0 - 9.0 * (ease - MIN_EASE) / (MAX_EASE - MIN_EASE),
+            last_review: Some(last_review),
+        }
+    }
+}
+
+fn read_cards(collection: &Collection, package_path: &str) -> Result<Vec<AnkiCard>, IzeError> {
+    let error = format_error(package_path);
+
+    let mut statement = collection
+        .connection()
+        .prepare(
+            "SELECT c.nid, c.ord, c.type, c.due, c.ivl, c.factor, c.reps, c.lapses,
+                (SELECT MAX(r.id) FROM revlog r WHERE r.cid = c.id)
+            FROM cards c
+            WHERE c.type != 0",
+        )
+        .map_err(&error)?;
+
+    let cards = statement
+        .query_map([], |row| {
+            Ok(AnkiCard {
+                note_id: row.get::<_, i64>(0)? as usize,
+                ordinal: row.get::<_, i64>(1)? as usize,
+                card_type: row.get(2)?,
+                due: row.get(3)?,
+                interval: row.get(4)?,
+                factor: row.get(5)?,
+                repetitions: row.get(6)?,
+                lapses: row.get(7)?,
+                last_review: row.get(8)?,
+            })
+        })
+        .map_err(&error)?
+        .collect::<Result<_, _>>()
+        .map_err(&error)?;
+
+    Ok(cards)
+}
+
+/// The box for a card which Anki reviews every `interval` days: the least frequently reviewed
+/// box that is still reviewed at least that often. Never the first box, which holds cards still
+/// being learned.
+fn box_for_interval(run: &PracticeRun, interval: u64) -> usize {
+    let last = run.boxes.len() - 1;
+    let index = run
+        .boxes
+        .iter()
+        .rposition(|card_box| card_box.review_frequency as u64 <= interval)
+        .unwrap_or(0);
+
+    index.clamp(last.min(1), last)
+}
+
+/// Bring the review state of a package's cards into a run of its deck.
+///
+/// Cards Anki is still learning go into the first box and reviewed cards into a box matching
+/// their interval. New cards are left where they are. In a run over several decks the package has
+/// to be one of them.
+pub fn import_review_state(package_path: &str, run: &mut PracticeRun) -> Result<(), IzeError> {
+    let collection = open_collection(package_path)?;
+
+    let creation_seconds: i64 = collection
+        .connection()
+        .query_row("SELECT crt FROM col", [], |row| row.get(0))
+        .map_err(format_error(package_path))?;
+    let creation_day = creation_seconds.max(0) as u64 / SECONDS_PER_DAY;
+
+    let cards = read_cards(&collection, package_path)?;
+    let deck = run
+        .deck_paths()
+        .iter()
+        .position(|path| *path == package_path)
+        .unwrap_or(0);
+
+    let mut states = HashMap::new();
+    for card in cards.iter() {
+        let schedule = card.schedule(creation_day);
+        let destination = if card.is_learning() {
+            0
+        } else {
+            box_for_interval(run, schedule.interval)
+        };
+
+        // Plain notes are reviewed through their first card, cloze notes through one item per
+        // card. Only the ids the run actually uses are moved below.
+        let card_id = deck_item_id(deck, card.note_id);
+        states.insert(
+            item_id(card_id, ItemKind::Cloze(card.ordinal + 1)),
+            (destination, schedule),
+        );
+        if card.ordinal == 0 {
+            states.insert(card_id, (destination, schedule));
+        }
+    }
+
+    // Only cards which are part of the run are moved.
+    let mut found = vec![];
+    let lists = std::iter::once(&mut run.remaining)
+        .chain(run.boxes.iter_mut().map(|card_box| &mut card_box.ids));
+    for list in lists {
+        list.retain(|id| {
+            let imported = states.contains_key(id);
+            if imported {
+                found.push(*id);
+            }
+            !imported
+        });
+    }
+
+    found.sort();
+    for id in found {
+        let (destination, schedule) = states[&id];
+        run.origins.remove(&id);
+        run.boxes[destination].ids.push(id);
+        run.schedule.cards.insert(id, schedule);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,254 @@
+//! Checking answers typed by the learner against the back of a card.
+
+use crate::{run_actions::RunCategory, PracticeRun};
+
+/// Differences which don't make a typed answer wrong.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnswerOptions {
+    pub ignore_case: bool,
+    pub ignore_whitespace: bool,
+    pub ignore_punctuation: bool,
+}
+
+impl Default for AnswerOptions {
+    fn default() -> Self {
+        AnswerOptions {
+            ignore_case: true,
+            ignore_whitespace: true,
+            ignore_punctuation: true,
+        }
+    }
+}
+
+impl AnswerOptions {
+    /// Every character counts.
+    pub const EXACT: AnswerOptions = AnswerOptions {
+        ignore_case: false,
+        ignore_whitespace: false,
+        ignore_punctuation: false,
+    };
+
+    fn normalize(&self, text: &str) -> String {
+        let mut normalized = String::new();
+        for c in text.trim().chars() {
+            // Anything but letters, digits and spaces, so "¿" and "«" go as well as "?".
+            if self.ignore_punctuation && !(c.is_alphanumeric() || c.is_whitespace()) {
+                continue;
+            }
+
+            if self.ignore_whitespace && c.is_whitespace() {
+                // Keep words apart so "a b" and "ab" stay different.
+                if !normalized.ends_with(' ') {
+                    normalized.push(' ');
+                }
+                continue;
+            }
+
+            if self.ignore_case {
+                normalized.extend(c.to_lowercase());
+            } else {
+                normalized.push(c);
+            }
+        }
+
+        normalized.trim().to_string()
+    }
+
+    fn same_char(&self, a: char, b: char) -> bool {
+        if self.ignore_case {
+            a.to_lowercase().eq(b.to_lowercase())
+        } else {
+            a == b
+        }
+    }
+}
+
+/// A run of characters in the comparison of a typed answer with the expected one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diff {
+    Same(String),
+    /// Expected but not typed.
+    Missing(String),
+    /// Typed but not expected.
+    Extra(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnswerCheck {
+    pub correct: bool,
+    pub diff: Vec<Diff>,
+}
+
+fn push_diff(diff: &mut Vec<Diff>, part: Diff) {
+    match (diff.last_mut(), &part) {
+        (Some(Diff::Same(text)), Diff::Same(c))
+        | (Some(Diff::Missing(text)), Diff::Missing(c))
+        | (Some(Diff::Extra(text)), Diff::Extra(c)) => text.push_str(c),
+        _ => diff.push(part),
+    }
+}
+
+/// The table of common subsequences has a cell for every pair of characters, so longer answers
+/// are only compared whole.
+const MAX_DIFF_CELLS: usize = 250_000;
+
+/// Character level diff from the longest common subsequence of the two texts.
+fn char_diff(typed: &str, expected: &str, options: &AnswerOptions) -> Vec<Diff> {
+    let typed: Vec<char> = typed.trim().chars().collect();
+    let expected: Vec<char> = expected.trim().chars().collect();
+
+    if (typed.len() + 1).saturating_mul(expected.len() + 1) > MAX_DIFF_CELLS {
+        return whole_diff(&typed, &expected, options);
+    }
+
+    // common[i][j] is the length of the longest common subsequence of typed[i..] and expected[j..].
+    let mut common = vec![vec![0usize; expected.len() + 1]; typed.len() + 1];
+    for i in (0..typed.len()).rev() {
+        for j in (0..expected.len()).rev() {
+            common[i][j] = if options.same_char(typed[i], expected[j]) {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < typed.len() || j < expected.len() {
+        if i < typed.len() && j < expected.len() && options.same_char(typed[i], expected[j]) {
+            // Show the expected spelling of matching characters.
+            push_diff(&mut diff, Diff::Same(expected[j].to_string()));
+            i += 1;
+            j += 1;
+        } else if j < expected.len() && (i == typed.len() || common[i][j + 1] >= common[i + 1][j]) {
+            push_diff(&mut diff, Diff::Missing(expected[j].to_string()));
+            j += 1;
+        } else {
+            push_diff(&mut diff, Diff::Extra(typed[i].to_string()));
+            i += 1;
+        }
+    }
+
+    diff
+}
+
+/// The typed answer against the expected one, without lining up their characters.
+fn whole_diff(typed: &[char], expected: &[char], options: &AnswerOptions) -> Vec<Diff> {
+    let same = typed.len() == expected.len()
+        && typed
+            .iter()
+            .zip(expected)
+            .all(|(a, b)| options.same_char(*a, *b));
+    if same {
+        return vec![Diff::Same(expected.iter().collect())];
+    }
+
+    let mut diff = vec![];
+    if !typed.is_empty() {
+        diff.push(Diff::Extra(typed.iter().collect()));
+    }
+    if !expected.is_empty() {
+        diff.push(Diff::Missing(expected.iter().collect()));
+    }
+
+    diff
+}
+
+pub fn check_answer(typed: &str, expected: &str, options: &AnswerOptions) -> AnswerCheck {
+    AnswerCheck {
+        correct: options.normalize(typed) == options.normalize(expected),
+        diff: char_diff(typed, expected, options),
+    }
+}
+
+impl PracticeRun {
+    /// Where a card in the remaining pile should go after a checked answer.
+    pub fn suggested_category(&self, id: usize, correct: bool) -> RunCategory {
+        if correct {
+            self.promotion_target(id)
+        } else {
+            self.demotion_target(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(text: &str) -> Diff {
+        Diff::Same(text.to_string())
+    }
+
+    fn missing(text: &str) -> Diff {
+        Diff::Missing(text.to_string())
+    }
+
+    fn extra(text: &str) -> Diff {
+        Diff::Extra(text.to_string())
+    }
+
+    #[test]
+    fn case_spacing_and_punctuation_are_ignored_by_default() {
+        let options = AnswerOptions::default();
+
+        assert_eq!(
+            options.normalize("  The  end,\tat last! "),
+            "the end at last"
+        );
+        assert!(check_answer("the END", "The end.", &options).correct);
+        assert!(!check_answer("theend", "the end", &options).correct);
+    }
+
+    #[test]
+    fn exact_answers_count_every_character() {
+        let options = AnswerOptions::EXACT;
+
+        assert_eq!(options.normalize(" A,  b "), "A,  b");
+        assert!(!check_answer("the END", "The end.", &options).correct);
+    }
+
+    #[test]
+    fn diffs_show_missing_and_extra_characters() {
+        let check = check_answer("wnet", "went", &AnswerOptions::EXACT);
+
+        assert_eq!(
+            check.diff,
+            vec![same("w"), missing("e"), same("n"), extra("e"), same("t")]
+        );
+    }
+
+    #[test]
+    fn diffs_show_the_expected_spelling_of_matching_characters() {
+        let check = check_answer("paris", "Paris", &AnswerOptions::default());
+
+        assert_eq!(check.diff, vec![same("Paris")]);
+    }
+
+    #[test]
+    fn punctuation_outside_ascii_is_ignored() {
+        let options = AnswerOptions::default();
+
+        assert!(!check_answer("que tal", "¿Qué tal?", &options).correct);
+        assert!(check_answer("qué tal", "¿Qué tal?", &options).correct);
+        assert!(check_answer("bonjour", "« Bonjour ! »", &options).correct);
+        assert!(check_answer("its", "it’s", &options).correct);
+    }
+
+    #[test]
+    fn long_answers_are_compared_whole() {
+        let expected = "went ".repeat(200);
+        let typed = "wnet ".repeat(200);
+        let options = AnswerOptions::default();
+
+        let check = check_answer(&typed, &expected, &options);
+        let matching = check_answer(&expected.to_uppercase(), &expected, &options);
+
+        assert_eq!(
+            check.diff,
+            vec![extra(typed.trim()), missing(expected.trim())]
+        );
+        assert_eq!(matching.diff, vec![same(expected.trim())]);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// How deck and run files are replaced when saving.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaveOptions {
+    /// Number of previous versions kept as `<file>.bak`, `<file>.bak.1`, ... with the most
+    /// recent first. Zero disables backups.
+    pub backups: usize,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        SaveOptions { backups: 1 }
+    }
+}
+
+/// A save which failed part way. The file being saved is left untouched in every case.
+#[derive(Debug)]
+pub enum SaveError {
+    /// The temporary file next to the target could not be created.
+    CreateTemp { path: PathBuf, source: io::Error },
+    /// Writing the contents failed, e.g. because the disk is full.
+    Write { path: PathBuf, source: io::Error },
+    /// The contents could not be flushed to disk.
+    Sync { path: PathBuf, source: io::Error },
+    /// The previous version could not be copied to its backup.
+    Backup { path: PathBuf, source: io::Error },
+    /// The temporary file could not be moved over the target.
+    Rename { path: PathBuf, source: io::Error },
+}
+
+impl SaveError {
+    /// The file the failed operation was acting on.
+    pub fn path(&self) -> &Path {
+        match self {
+            SaveError::CreateTemp { path, .. }
+            | SaveError::Write { path, .. }
+            | SaveError::Sync { path, .. }
+            | SaveError::Backup { path, .. }
+            | SaveError::Rename { path, .. } => path,
+        }
+    }
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (action, source) = match self {
+            SaveError::CreateTemp { source, .. } => ("create temporary file", source),
+            SaveError::Write { source, .. } => ("write", source),
+            SaveError::Sync { source, .. } => ("flush to disk", source),
+            SaveError::Backup { source, .. } => ("back up", source),
+            SaveError::Rename { source, .. } => ("replace", source),
+        };
+
+        write!(
+            f,
+            "Could not {} '{}': {}",
+            action,
+            self.path().display(),
+            source
+        )
+    }
+}
+
+impl Error for SaveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SaveError::CreateTemp { source, .. }
+            | SaveError::Write { source, .. }
+            | SaveError::Sync { source, .. }
+            | SaveError::Backup { source, .. }
+            | SaveError::Rename { source, .. } => Some(source),
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    match index {
+        0 => with_suffix(path, ".bak"),
+        _ => with_suffix(path, &format!(".bak.{index}")),
+    }
+}
+
+/// Shift the existing backups down by one and copy the current file into the first.
+fn rotate_backups(path: &Path, backups: usize) -> Result<(), SaveError> {
+    if backups == 0 || !path.exists() {
+        return Ok(());
+    }
+
+    for index in (1..backups).rev() {
+        let older = backup_path(path, index - 1);
+        if older.exists() {
+            let newer = backup_path(path, index);
+            fs::rename(&older, &newer).map_err(|source| SaveError::Backup {
+                path: newer,
+                source,
+            })?;
+        }
+    }
+
+    let backup = backup_path(path, 0);
+    fs::copy(path, &backup).map_err(|source| SaveError::Backup {
+        path: backup,
+        source,
+    })?;
+
+    Ok(())
+}
+
+/// Make the rename durable by syncing the directory entry as well.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn write_temp(
+    temp_path: &Path,
+    write: impl FnOnce(&mut BufWriter<&File>) -> io::Result<()>,
+) -> Result<(), SaveError> {
+    let file = File::create(temp_path).map_err(|source| SaveError::CreateTemp {
+        path: temp_path.to_path_buf(),
+        source,
+    })?;
+
+    let mut writer = BufWriter::new(&file);
+    write(&mut writer)
+        .and_then(|_| writer.flush())
+        .map_err(|source| SaveError::Write {
+            path: temp_path.to_path_buf(),
+            source,
+        })?;
+    drop(writer);
+
+    file.sync_all().map_err(|source| SaveError::Sync {
+        path: temp_path.to_path_buf(),
+        source,
+    })
+}
+
+/// Replace the file at `path` with the output of `write`.
+///
+/// The contents are written to a temporary file next to the target, synced and then renamed over
+/// it, so a crash or a full disk leaves either the old or the new file but never a truncated one.
+pub(crate) fn write_atomically(
+    path: &str,
+    options: &SaveOptions,
+    write: impl FnOnce(&mut BufWriter<&File>) -> io::Result<()>,
+) -> Result<(), SaveError> {
+    let path = Path::new(path);
+    let temp_path = with_suffix(path, ".tmp");
+
+    let result = write_temp(&temp_path, write)
+        .and_then(|_| rotate_backups(path, options.backups))
+        .and_then(|_| {
+            fs::rename(&temp_path, path).map_err(|source| SaveError::Rename {
+                path: path.to_path_buf(),
+                source,
+            })
+        });
+
+    if result.is_err() {
+        _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    sync_parent(path).map_err(|source| SaveError::Sync {
+        path: path.to_path_buf(),
+        source,
+    })
+}
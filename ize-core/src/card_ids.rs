@@ -0,0 +1,195 @@
+//! Ids for cards written without one.
+//!
+//! Run files refer to cards by id, so a card has to keep its id from one load of the deck to the
+//! next. Numbering cards in order works until a card is inserted before others, hashing the front
+//! keeps the id as long as the front is unchanged, and random ids never depend on the content.
+//! Stamping a deck writes the ids into the file so they no longer change at all. A run only loads
+//! decks whose cards all have ids in the file, as a run which kept ids the file doesn't have would
+//! lose track of its cards the next time the deck is loaded.
+
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use rand::random;
+
+use crate::{error::IzeError, item::MAX_CARD_ID, Card, Deck};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum IdScheme {
+    /// Numbered after the highest id in the deck.
+    #[default]
+    Sequential,
+    /// Derived from the front of the card.
+    Hash,
+    /// Random, like a UUID cut down to the size of a card id.
+    Random,
+}
+
+impl IdScheme {
+    pub const ALL: [IdScheme; 3] = [IdScheme::Sequential, IdScheme::Hash, IdScheme::Random];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IdScheme::Sequential => "sequential",
+            IdScheme::Hash => "hash",
+            IdScheme::Random => "random",
+        }
+    }
+
+    pub fn is_sequential(&self) -> bool {
+        *self == IdScheme::Sequential
+    }
+}
+
+impl Display for IdScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for IdScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        IdScheme::ALL
+            .into_iter()
+            .find(|scheme| scheme.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown id scheme '{}'", s.trim()))
+    }
+}
+
+/// FNV-1a, which unlike the standard library's hasher is the same in every build.
+fn content_hash(text: &str) -> usize {
+    let hash = text.trim().bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    });
+
+    (hash as usize) & MAX_CARD_ID
+}
+
+/// A card id written in a deck which can't be used.
+#[derive(Debug)]
+pub(crate) enum CardIdError {
+    /// Used twice in the same deck. The lines are 0 if the format has no lines to point at.
+    Duplicate {
+        id: usize,
+        /// Where the second use is.
+        line: usize,
+        first_line: usize,
+    },
+    /// Above [`MAX_CARD_ID`], so it doesn't fit in an item id.
+    TooLarge { id: usize, line: usize },
+}
+
+impl Display for CardIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CardIdError::Duplicate { id, first_line, .. } if *first_line > 0 => {
+                write!(f, "Card id {id} is already used on line {first_line}.")
+            }
+            CardIdError::Duplicate { id, .. } => {
+                write!(f, "Card id {id} is used by more than one card.")
+            }
+            CardIdError::TooLarge { id, .. } => {
+                write!(f, "Card id {id} is above the highest id, {MAX_CARD_ID}.")
+            }
+        }
+    }
+}
+
+impl CardIdError {
+    pub(crate) fn into_error(self, path: &str) -> IzeError {
+        let line = match self {
+            CardIdError::Duplicate { line, .. } | CardIdError::TooLarge { line, .. } => line,
+        };
+        IzeError::parse(path, line, "", self.to_string())
+    }
+}
+
+/// A card as read from a deck file, before it has an id.
+pub(crate) struct ReadCard {
+    pub card_id: Option<usize>,
+    /// Line the card starts on, or 0.
+    pub line: usize,
+    pub card: Card,
+}
+
+impl Deck {
+    /// An id for a new card with the given front which no card in the deck uses.
+    pub fn new_card_id(&self, front: &str) -> usize {
+        let mut next = self.next_card_id();
+        self.id_scheme
+            .generate(front, &mut next, |id| self.cards.contains_key(&id))
+    }
+
+    /// Add the cards to the deck, giving ids to those without one. Ids written in the file are
+    /// never changed, so an id used twice is an error rather than one card replacing the other.
+    pub(crate) fn add_read_cards(&mut self, cards: Vec<ReadCard>) -> Result<(), CardIdError> {
+        let mut lines: HashMap<usize, usize> = HashMap::new();
+        let mut unnumbered = vec![];
+
+        for read in cards {
+            let Some(card_id) = read.card_id else {
+                unnumbered.push(read.card);
+                continue;
+            };
+
+            if card_id > MAX_CARD_ID {
+                return Err(CardIdError::TooLarge {
+                    id: card_id,
+                    line: read.line,
+                });
+            }
+            if let Some(first_line) = lines.insert(card_id, read.line) {
+                return Err(CardIdError::Duplicate {
+                    id: card_id,
+                    line: read.line,
+                    first_line,
+                });
+            }
+            self.cards.insert(
+                card_id,
+                Card {
+                    card_id,
+                    ..read.card
+                },
+            );
+        }
+
+        let mut next = self.next_card_id();
+        for card in unnumbered {
+            let card_id = self
+                .id_scheme
+                .generate(&card.front, &mut next, |id| self.cards.contains_key(&id));
+            self.cards.insert(card_id, Card { card_id, ..card });
+            self.generated_ids += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl IdScheme {
+    /// An id not `taken`, moving on to the next id on a clash.
+    fn generate(self, front: &str, next: &mut usize, taken: impl Fn(usize) -> bool) -> usize {
+        let mut id = match self {
+            IdScheme::Sequential => *next,
+            IdScheme::Hash => content_hash(front),
+            IdScheme::Random => random::<usize>() & MAX_CARD_ID,
+        };
+
+        while id == 0 || taken(id) {
+            id = (id + 1) & MAX_CARD_ID;
+        }
+        *next = (*next).max(id + 1);
+
+        id
+    }
+}
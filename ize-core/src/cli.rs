@@ -0,0 +1,58 @@
+//! Command line arguments understood by every front end.
+
+/// Split `--seed <number>` or `--seed=<number>` off command line arguments, returning the seed
+/// and the other arguments.
+pub fn take_seed_arg(args: Vec<String>) -> Result<(Option<u64>, Vec<String>), String> {
+    let mut seed = None;
+    let mut rest = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--seed") {
+            Some("") => args.next().unwrap_or_default(),
+            Some(value) if value.starts_with('=') => value[1..].to_string(),
+            _ => {
+                rest.push(arg);
+                continue;
+            }
+        };
+
+        seed = Some(
+            value
+                .parse()
+                .map_err(|_| format!("Seed '{value}' must be a whole number."))?,
+        );
+    }
+
+    Ok((seed, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn seeds_are_taken_from_either_form() {
+        assert_eq!(
+            take_seed_arg(args(&["a.run", "--seed", "7"])),
+            Ok((Some(7), args(&["a.run"])))
+        );
+        assert_eq!(
+            take_seed_arg(args(&["--seed=7", "a.run"])),
+            Ok((Some(7), args(&["a.run"])))
+        );
+        assert_eq!(
+            take_seed_arg(args(&["a.run"])),
+            Ok((None, args(&["a.run"])))
+        );
+    }
+
+    #[test]
+    fn seeds_must_be_numbers() {
+        assert!(take_seed_arg(args(&["--seed"])).is_err());
+        assert!(take_seed_arg(args(&["--seed=-1"])).is_err());
+    }
+}
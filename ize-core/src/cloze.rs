@@ -0,0 +1,194 @@
+//! Cloze deletion cards, where the front is a text with terms marked as `{{c1::term}}` or
+//! `{{c1::term::hint}}`.
+//!
+//! Each cloze number is reviewed as its own item: the front hides every term with that number and
+//! the back reveals them. A card is a cloze card as soon as its front holds a cloze, so decks
+//! imported from Anki cloze notes work unchanged.
+
+const CLOZE_START: &str = "{{c";
+const CLOZE_SEPARATOR: &str = "::";
+const CLOZE_END: &str = "}}";
+/// Item ids have room for cloze numbers up to this.
+const MAX_CLOZE_NUMBER: usize = 255;
+
+/// A piece of a card side as it should be shown.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Span {
+    Text(String),
+    /// A hidden term, shown as `[...]` or as its hint.
+    Blank(String),
+    /// The term hidden on the front, revealed on the back.
+    Answer(String),
+}
+
+impl Span {
+    pub fn text(&self) -> &str {
+        match self {
+            Span::Text(text) | Span::Blank(text) | Span::Answer(text) => text,
+        }
+    }
+}
+
+/// The side's text without any highlighting.
+pub fn plain_text(spans: &[Span]) -> String {
+    spans.iter().map(|span| span.text()).collect()
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Cloze {
+        number: usize,
+        answer: &'a str,
+        hint: Option<&'a str>,
+    },
+}
+
+/// Parse the cloze at the start of `text`, returning it and the number of bytes it takes up.
+fn parse_cloze(text: &str) -> Option<(Piece<'_>, usize)> {
+    let after_start = text.strip_prefix(CLOZE_START)?;
+    let digits = after_start
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(after_start.len());
+    let number = after_start[..digits]
+        .parse()
+        .ok()
+        .filter(|n| (1..=MAX_CLOZE_NUMBER).contains(n))?;
+
+    let body = after_start[digits..].strip_prefix(CLOZE_SEPARATOR)?;
+    let end = body.find(CLOZE_END)?;
+    let (answer, hint) = match body[..end].split_once(CLOZE_SEPARATOR) {
+        Some((answer, hint)) => (answer, Some(hint)),
+        None => (&body[..end], None),
+    };
+
+    let length = text.len() - body.len() + end + CLOZE_END.len();
+    Some((
+        Piece::Cloze {
+            number,
+            answer,
+            hint,
+        },
+        length,
+    ))
+}
+
+fn parse(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find(CLOZE_START) {
+        match parse_cloze(&rest[start..]) {
+            Some((cloze, length)) => {
+                if start > 0 {
+                    pieces.push(Piece::Text(&rest[..start]));
+                }
+                pieces.push(cloze);
+                rest = &rest[start + length..];
+            }
+            None => {
+                let skip = start + CLOZE_START.len();
+                pieces.push(Piece::Text(&rest[..skip]));
+                rest = &rest[skip..];
+            }
+        }
+    }
+
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+
+    pieces
+}
+
+/// The distinct cloze numbers in `text`, in ascending order.
+pub fn cloze_numbers(text: &str) -> Vec<usize> {
+    let mut numbers: Vec<usize> = parse(text)
+        .into_iter()
+        .filter_map(|piece| match piece {
+            Piece::Cloze { number, .. } => Some(number),
+            Piece::Text(_) => None,
+        })
+        .collect();
+    numbers.sort();
+    numbers.dedup();
+
+    numbers
+}
+
+pub fn is_cloze(text: &str) -> bool {
+    !cloze_numbers(text).is_empty()
+}
+
+fn spans(text: &str, number: usize, hidden: bool) -> Vec<Span> {
+    parse(text)
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Text(text) => Span::Text(text.to_string()),
+            Piece::Cloze {
+                number: n, answer, ..
+            } if n != number => Span::Text(answer.to_string()),
+            Piece::Cloze { hint, .. } if hidden => {
+                Span::Blank(format!("[{}]", hint.unwrap_or("...")))
+            }
+            Piece::Cloze { answer, .. } => Span::Answer(answer.to_string()),
+        })
+        .collect()
+}
+
+/// The text with the terms of cloze `number` blanked out.
+pub fn front_spans(text: &str, number: usize) -> Vec<Span> {
+    spans(text, number, true)
+}
+
+/// The text with the terms of cloze `number` highlighted.
+pub fn back_spans(text: &str, number: usize) -> Vec<Span> {
+    spans(text, number, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_are_shown_in_place_of_their_term() {
+        let text = "{{c1::Paris::city}} is in {{c2::France}}.";
+
+        assert_eq!(
+            front_spans(text, 1),
+            vec![
+                Span::Blank("[city]".to_string()),
+                Span::Text(" is in ".to_string()),
+                Span::Text("France".to_string()),
+                Span::Text(".".to_string()),
+            ]
+        );
+        assert_eq!(plain_text(&front_spans(text, 2)), "Paris is in [...].");
+        assert_eq!(plain_text(&back_spans(text, 1)), "Paris is in France.");
+        assert_eq!(back_spans(text, 2)[2], Span::Answer("France".to_string()));
+    }
+
+    #[test]
+    fn each_number_is_counted_once() {
+        assert_eq!(cloze_numbers("{{c2::a}} {{c1::b}} {{c2::c}}"), vec![1, 2]);
+        assert!(!is_cloze("no clozes {{here}}"));
+    }
+
+    #[test]
+    fn numbers_past_the_item_id_limit_are_plain_text() {
+        let text = "{{c255::last}} {{c256::too far}} {{c0::zero}}";
+
+        assert_eq!(cloze_numbers(text), vec![255]);
+        assert_eq!(
+            plain_text(&front_spans(text, 255)),
+            "[...] {{c256::too far}} {{c0::zero}}"
+        );
+    }
+
+    #[test]
+    fn unclosed_clozes_are_plain_text() {
+        let text = "{{c1::never closed";
+
+        assert!(!is_cloze(text));
+        assert_eq!(plain_text(&front_spans(text, 1)), text);
+    }
+}
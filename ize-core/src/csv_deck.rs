@@ -0,0 +1,645 @@
+//! Decks kept as CSV or TSV spreadsheets, one card per row.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    atomic_write::{write_atomically, SaveOptions},
+    card_ids::{IdScheme, ReadCard},
+    error::{line_and_column, IzeError},
+    item::Direction,
+    tags::parse_tags,
+    Card, Deck,
+};
+
+/// A column picked either by its position or by its name in the header row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+impl Column {
+    fn name(name: &str) -> Self {
+        Column::Name(name.to_string())
+    }
+
+    /// The position of the column, or `None` if it isn't in the file.
+    fn resolve(&self, header: Option<&csv::StringRecord>) -> Option<usize> {
+        match self {
+            Column::Index(index) => Some(*index),
+            Column::Name(name) => header?
+                .iter()
+                .position(|field| field.trim().eq_ignore_ascii_case(name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    /// Character used to quote fields, or `None` to read and write fields verbatim.
+    pub quote: Option<u8>,
+    pub has_header: bool,
+    /// Rows without an id, or every row if the column is missing, are given new ids.
+    pub id_column: Option<Column>,
+    /// How new ids are made, since the file has nowhere to keep the deck's scheme.
+    pub id_scheme: IdScheme,
+    /// Named columns missing from the header fall back to position: the front is the first
+    /// column other than the id and the back the second.
+    pub front_column: Column,
+    pub back_column: Column,
+    /// Column holding each card's direction, if the file has one.
+    pub direction_column: Option<Column>,
+    /// Column holding each card's tags separated by spaces, if the file has one.
+    pub tags_column: Option<Column>,
+    /// Columns added to the back of the card, one line each.
+    pub extra_columns: Vec<Column>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            quote: Some(b'"'),
+            has_header: true,
+            id_column: Some(Column::name("id")),
+            id_scheme: IdScheme::Sequential,
+            front_column: Column::name("front"),
+            back_column: Column::name("back"),
+            direction_column: Some(Column::name("direction")),
+            tags_column: Some(Column::name("tags")),
+            extra_columns: vec![],
+        }
+    }
+}
+
+impl CsvOptions {
+    /// The default options for the file's extension: tab separated for `.tsv`, otherwise commas.
+    pub fn for_path(path: &str) -> Self {
+        let delimiter = match extension(path).as_deref() {
+            Some("tsv") => b'\t',
+            _ => b',',
+        };
+
+        CsvOptions {
+            delimiter,
+            ..Default::default()
+        }
+    }
+}
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+pub fn is_delimited(path: &str) -> bool {
+    matches!(extension(path).as_deref(), Some("csv" | "tsv"))
+}
+
+struct ColumnMap {
+    id: Option<usize>,
+    front: usize,
+    back: usize,
+    direction: Option<usize>,
+    tags: Option<usize>,
+    extras: Vec<(usize, Option<String>)>,
+}
+
+impl ColumnMap {
+    fn new(options: &CsvOptions, header: Option<&csv::StringRecord>) -> Self {
+        let id = options
+            .id_column
+            .as_ref()
+            .and_then(|column| column.resolve(header));
+
+        let mut positions = (0..).filter(|index| Some(*index) != id);
+        let front = options
+            .front_column
+            .resolve(header)
+            .unwrap_or_else(|| positions.next().unwrap_or_default());
+        let back = options
+            .back_column
+            .resolve(header)
+            .unwrap_or_else(|| positions.find(|index| *index != front).unwrap_or_default());
+
+        let direction = options
+            .direction_column
+            .as_ref()
+            .and_then(|column| column.resolve(header));
+
+        let tags = options
+            .tags_column
+            .as_ref()
+            .and_then(|column| column.resolve(header));
+
+        let extras = options
+            .extra_columns
+            .iter()
+            .filter_map(|column| {
+                let index = column.resolve(header)?;
+                let name = header.and_then(|header| header.get(index));
+                Some((index, name.map(|name| name.trim().to_string())))
+            })
+            .collect();
+
+        ColumnMap {
+            id,
+            front,
+            back,
+            direction,
+            tags,
+            extras,
+        }
+    }
+}
+
+fn reader_builder(options: &CsvOptions) -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(options.delimiter)
+        .has_headers(options.has_header)
+        .flexible(true)
+        .quoting(options.quote.is_some());
+    if let Some(quote) = options.quote {
+        builder.quote(quote);
+    }
+
+    builder
+}
+
+fn csv_error(path: &str, error: csv::Error) -> IzeError {
+    let position = error.position().cloned();
+    let message = error.to_string();
+    if let csv::ErrorKind::Io(e) = error.into_kind() {
+        return IzeError::io(path, e);
+    }
+
+    let Some(position) = position else {
+        return IzeError::parse(path, 0, "", message);
+    };
+    // The position only counts bytes, so the column is found in the file itself.
+    let column = fs::read(path).map_or(0, |bytes| {
+        line_and_column(&bytes, position.byte() as usize).1
+    });
+    IzeError::parse(path, position.line() as usize, "", message).at_column(column)
+}
+
+fn field(record: &csv::StringRecord, index: usize) -> String {
+    record.get(index).unwrap_or_default().to_string()
+}
+
+pub fn load_csv_deck(path: &str, options: &CsvOptions) -> Result<Deck, IzeError> {
+    let mut reader = reader_builder(options)
+        .from_path(path)
+        .map_err(|e| csv_error(path, e))?;
+
+    let header = if options.has_header {
+        Some(reader.headers().map_err(|e| csv_error(path, e))?.clone())
+    } else {
+        None
+    };
+    let columns = ColumnMap::new(options, header.as_ref());
+
+    // Ids are assigned after reading so that generated ones can't collide with later rows.
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| csv_error(path, e))?;
+        let line = record
+            .position()
+            .map_or(0, |position| position.line() as usize);
+
+        let id = match columns.id.map(|index| field(&record, index)) {
+            Some(id) if !id.trim().is_empty() => {
+                let id = id.trim().parse().map_err(|_| {
+                    IzeError::parse(path, line, &id, format!("Card id {id} must be a number."))
+                })?;
+                Some(id)
+            }
+            _ => None,
+        };
+
+        let direction = match columns.direction.map(|index| field(&record, index)) {
+            Some(direction) if !direction.trim().is_empty() => Some(
+                direction
+                    .parse()
+                    .map_err(|e| IzeError::parse(path, line, &direction, e))?,
+            ),
+            _ => None,
+        };
+
+        let mut back = field(&record, columns.back);
+        for (index, name) in columns.extras.iter() {
+            let value = field(&record, *index);
+            if value.trim().is_empty() {
+                continue;
+            }
+
+            back.push('\n');
+            if let Some(name) = name {
+                back.push_str(&format!("{name}: "));
+            }
+            back.push_str(&value);
+        }
+
+        let tags = columns
+            .tags
+            .map_or(vec![], |index| parse_tags(&field(&record, index)));
+
+        rows.push(ReadCard {
+            card_id: id,
+            line,
+            card: Card {
+                card_id: 0,
+                front: field(&record, columns.front),
+                back,
+                direction,
+                tags,
+            },
+        });
+    }
+
+    let mut deck = Deck::new();
+    deck.id_scheme = options.id_scheme;
+    deck.add_read_cards(rows).map_err(|e| e.into_error(path))?;
+
+    Ok(deck)
+}
+
+/// The header written for `fields`, with columns given by index at their index and named columns
+/// in the first free places, in the order given.
+fn header_record(fields: &[(&Column, CardField)]) -> csv::StringRecord {
+    let mut header: Vec<Option<String>> = vec![];
+    for (column, field) in fields {
+        if let Column::Index(index) = column {
+            if header.len() <= *index {
+                header.resize(index + 1, None);
+            }
+            header[*index] = Some(field.name().to_string());
+        }
+    }
+    for (column, _) in fields {
+        if let Column::Name(name) = column {
+            match header.iter().position(Option::is_none) {
+                Some(free) => header[free] = Some(name.clone()),
+                None => header.push(Some(name.clone())),
+            }
+        }
+    }
+
+    header.into_iter().map(Option::unwrap_or_default).collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CardField {
+    Id,
+    Front,
+    Back,
+    Direction,
+    Tags,
+}
+
+impl CardField {
+    fn name(self) -> &'static str {
+        match self {
+            CardField::Id => "id",
+            CardField::Front => "front",
+            CardField::Back => "back",
+            CardField::Direction => "direction",
+            CardField::Tags => "tags",
+        }
+    }
+}
+
+/// Where each field of a card is written, found the way [`load_csv_deck`] will look for it.
+struct WriteLayout {
+    header: Option<csv::StringRecord>,
+    fields: Vec<(usize, CardField)>,
+    width: usize,
+}
+
+impl WriteLayout {
+    fn new(options: &CsvOptions, directions: bool, tags: bool) -> io::Result<Self> {
+        let mut wanted = vec![];
+        if let Some(column) = &options.id_column {
+            wanted.push((column, CardField::Id));
+        }
+        wanted.push((&options.front_column, CardField::Front));
+        wanted.push((&options.back_column, CardField::Back));
+        if let Some(column) = options.direction_column.as_ref().filter(|_| directions) {
+            wanted.push((column, CardField::Direction));
+        }
+        if let Some(column) = options.tags_column.as_ref().filter(|_| tags) {
+            wanted.push((column, CardField::Tags));
+        }
+
+        let header = options.has_header.then(|| header_record(&wanted));
+        let columns = ColumnMap::new(options, header.as_ref());
+
+        // Fields the reader can't find without a header row are left out.
+        let fields: Vec<(usize, CardField)> = [
+            (columns.id, CardField::Id),
+            (Some(columns.front), CardField::Front),
+            (Some(columns.back), CardField::Back),
+            (
+                columns.direction.filter(|_| directions),
+                CardField::Direction,
+            ),
+            (columns.tags.filter(|_| tags), CardField::Tags),
+        ]
+        .into_iter()
+        .filter_map(|(index, field)| Some((index?, field)))
+        .collect();
+
+        for (i, (index, field)) in fields.iter().enumerate() {
+            let name = field.name();
+            let message = if let Some((_, other)) = fields[..i].iter().find(|(o, _)| o == index) {
+                format!(
+                    "The {} and {name} would both be written to column {index}.",
+                    other.name()
+                )
+            } else if columns.extras.iter().any(|(extra, _)| extra == index) {
+                format!("The {name} would be written to column {index}, which is read as extra.")
+            } else {
+                continue;
+            };
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+
+        let width = fields
+            .iter()
+            .map(|(index, _)| index + 1)
+            .max()
+            .unwrap_or_default();
+        let width = width.max(header.as_ref().map_or(0, |header| header.len()));
+
+        Ok(WriteLayout {
+            header,
+            fields,
+            width,
+        })
+    }
+}
+
+/// Without quotes, a field holding the delimiter or a line break would be read back as more than
+/// one.
+fn check_unquoted<'a>(
+    record: impl IntoIterator<Item = &'a str>,
+    options: &CsvOptions,
+) -> io::Result<()> {
+    if options.quote.is_some() {
+        return Ok(());
+    }
+
+    let delimiter = options.delimiter as char;
+    match record
+        .into_iter()
+        .find(|field| field.contains([delimiter, '\n', '\r']))
+    {
+        Some(field) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("'{field}' needs quotes, as it holds the delimiter or a line break."),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn write_csv_deck(w: &mut impl Write, deck: &Deck, options: &CsvOptions) -> io::Result<()> {
+    let mut builder = csv::WriterBuilder::new();
+    builder
+        .delimiter(options.delimiter)
+        .quote_style(match options.quote {
+            Some(_) => csv::QuoteStyle::Necessary,
+            None => csv::QuoteStyle::Never,
+        });
+    if let Some(quote) = options.quote {
+        builder.quote(quote);
+    }
+    let mut writer = builder.from_writer(w);
+
+    // CSV has nowhere to keep the deck's direction, so it is written out for every card.
+    let deck_direction = (deck.direction != Direction::Forward).then_some(deck.direction);
+    let direction = |card: &Card| card.direction.or(deck_direction);
+
+    // Only write directions and tags for decks which use them.
+    let directions = deck.cards.values().any(|card| direction(card).is_some());
+    let tags = deck.cards.values().any(|card| !card.tags.is_empty());
+
+    let layout = WriteLayout::new(options, directions, tags)?;
+    if let Some(header) = &layout.header {
+        check_unquoted(header, options)?;
+        writer.write_record(header)?;
+    }
+
+    // Sort to avoid churn in the format of the deck file
+    let mut cards: Vec<&Card> = deck.cards.values().collect();
+    cards.sort_by_key(|card| card.card_id);
+
+    for card in cards {
+        let mut record = vec![String::new(); layout.width];
+        for (index, field) in layout.fields.iter() {
+            record[*index] = match field {
+                CardField::Id => card.card_id.to_string(),
+                CardField::Front => card.front.clone(),
+                CardField::Back => card.back.clone(),
+                CardField::Direction => direction(card).map(|d| d.to_string()).unwrap_or_default(),
+                CardField::Tags => card.tags.join(" "),
+            };
+        }
+        check_unquoted(record.iter().map(String::as_str), options)?;
+        writer.write_record(record)?;
+    }
+
+    writer.flush()
+}
+
+/// Write the deck in the columns `options` picks, so that loading it with the same options reads
+/// it back. Columns given by name are placed after those given by index, in the order id, front,
+/// back, direction and tags, the last two only if any card has them. Without a header row, named
+/// columns other than the front and back can't be found again and are left out, ids included.
+/// Extra columns were read into the back of each card and are written as part of it.
+pub fn save_csv_deck(
+    path: &str,
+    deck: &Deck,
+    options: &CsvOptions,
+    save_options: &SaveOptions,
+) -> Result<(), IzeError> {
+    write_atomically(path, save_options, |w| write_csv_deck(w, deck, options))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Fields = (usize, String, String, Option<Direction>, Vec<String>);
+
+    fn test_path(name: &str, file: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("ize-csv-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(file).to_string_lossy().to_string()
+    }
+
+    fn remove_test_dir(path: &str) {
+        fs::remove_dir_all(Path::new(path).parent().unwrap()).unwrap();
+    }
+
+    fn verbs() -> Deck {
+        let mut deck = Deck::new();
+        deck.title = Some("Verbs".to_string());
+        deck.direction = Direction::Both;
+        let cards = [
+            (1, "go", "went\ngone", None, vec!["irregular", "verbs"]),
+            (2, "walk", "walked", Some(Direction::Forward), vec![]),
+            (7, "say, tell", "said", None, vec!["irregular"]),
+        ];
+        for (card_id, front, back, direction, tags) in cards {
+            let tags = tags.into_iter().map(str::to_string).collect();
+            deck.cards.insert(
+                card_id,
+                Card {
+                    card_id,
+                    front: front.to_string(),
+                    back: back.to_string(),
+                    direction,
+                    tags,
+                },
+            );
+        }
+
+        deck
+    }
+
+    /// The cards in id order, with the deck's direction given to the cards without one, as the
+    /// file has nowhere else to keep it.
+    fn fields(deck: &Deck) -> Vec<Fields> {
+        let deck_direction = (deck.direction != Direction::Forward).then_some(deck.direction);
+        let mut fields: Vec<Fields> = deck
+            .cards
+            .values()
+            .map(|card| {
+                (
+                    card.card_id,
+                    card.front.clone(),
+                    card.back.clone(),
+                    card.direction.or(deck_direction),
+                    card.tags.clone(),
+                )
+            })
+            .collect();
+        fields.sort_by_key(|card| card.0);
+        fields
+    }
+
+    fn round_trip(path: &str, options: &CsvOptions) -> Deck {
+        save_csv_deck(path, &verbs(), options, &SaveOptions::default()).unwrap();
+        load_csv_deck(path, options).unwrap()
+    }
+
+    #[test]
+    fn delimited_decks_round_trip_without_the_title() {
+        for (name, file) in [("comma", "deck.csv"), ("tab", "deck.tsv")] {
+            let path = test_path(name, file);
+
+            let loaded = round_trip(&path, &CsvOptions::for_path(&path));
+
+            assert_eq!(loaded.title, None, "{file}");
+            assert_eq!(fields(&loaded), fields(&verbs()), "{file}");
+            remove_test_dir(&path);
+        }
+    }
+
+    #[test]
+    fn columns_given_by_index_round_trip_without_a_header() {
+        let path = test_path("index", "deck.csv");
+        let options = CsvOptions {
+            has_header: false,
+            id_column: Some(Column::Index(2)),
+            front_column: Column::Index(0),
+            back_column: Column::Index(1),
+            direction_column: Some(Column::Index(4)),
+            tags_column: Some(Column::Index(3)),
+            ..Default::default()
+        };
+
+        let loaded = round_trip(&path, &options);
+
+        assert_eq!(fields(&loaded), fields(&verbs()));
+        remove_test_dir(&path);
+    }
+
+    #[test]
+    fn named_columns_follow_those_given_by_index() {
+        let path = test_path("mixed", "deck.csv");
+        let options = CsvOptions {
+            id_column: Some(Column::Index(3)),
+            front_column: Column::name("question"),
+            ..Default::default()
+        };
+
+        let loaded = round_trip(&path, &options);
+        let contents = fs::read_to_string(&path).unwrap();
+
+        assert_eq!(
+            contents.lines().next(),
+            Some("question,back,direction,id,tags")
+        );
+        assert_eq!(fields(&loaded), fields(&verbs()));
+        remove_test_dir(&path);
+    }
+
+    #[test]
+    fn two_fields_in_one_column_is_an_error() {
+        let path = test_path("clash", "deck.csv");
+        let options = CsvOptions {
+            front_column: Column::Index(1),
+            back_column: Column::Index(1),
+            ..Default::default()
+        };
+
+        let result = save_csv_deck(&path, &verbs(), &options, &SaveOptions::default());
+
+        assert!(result.is_err());
+        remove_test_dir(&path);
+    }
+
+    #[test]
+    fn fields_needing_quotes_are_an_error_without_quoting() {
+        let path = test_path("unquoted", "deck.csv");
+        let options = CsvOptions {
+            quote: None,
+            ..Default::default()
+        };
+
+        // "went\ngone" and "say, tell" can't be written without quotes.
+        let result = save_csv_deck(&path, &verbs(), &options, &SaveOptions::default());
+
+        assert!(result.is_err());
+        remove_test_dir(&path);
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_line_and_column() {
+        let path = test_path("invalid", "deck.csv");
+        fs::write(&path, b"id,front,back\n1,go,went\n2,\xff,walked\n").unwrap();
+
+        let result = load_csv_deck(&path, &CsvOptions::default());
+
+        assert!(matches!(
+            result,
+            Err(IzeError::Parse {
+                line: 3,
+                column: 1,
+                ..
+            })
+        ));
+        remove_test_dir(&path);
+    }
+}
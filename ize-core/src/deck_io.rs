@@ -1,235 +1,977 @@
 use std::{
     collections::{HashMap, HashSet},
-    error::Error,
     fmt::Display,
     fs::{self, File},
-    io::{BufRead, BufReader, BufWriter, Lines, Write},
+    io::{self, BufRead, BufReader, Lines, Write},
     iter::Peekable,
+    path::{Path, PathBuf},
 };
 
-use crate::{Card, Deck, PracticeRun};
+#[cfg(feature = "serde")]
+use crate::serde_io::{self, DataFormat};
+use crate::{
+    atomic_write::{write_atomically, SaveOptions},
+    card_ids::{IdScheme, ReadCard},
+    csv_deck::{is_delimited, load_csv_deck, save_csv_deck, CsvOptions},
+    error::IzeError,
+    item::{format_item_id, parse_item_id, Direction, MAX_DECKS},
+    leitner::{BoxLayout, CardBox},
+    run_actions::{format_learning_steps, parse_learning_steps},
+    scheduler::{parse_scheduler, CardSchedule, Schedule},
+    tags::parse_tags,
+    Card, Deck, PracticeRun,
+};
 
-#[derive(Debug)]
-struct ParsingError {
-    error_message: String,
+/// Reads a file line by line, keeping track of the line number for error messages.
+struct LineReader {
+    path: String,
+    lines: Peekable<Lines<BufReader<File>>>,
+    /// Number of lines consumed so far.
+    line_number: usize,
 }
 
-impl ParsingError {
-    pub fn new(error_message: String) -> Self {
-        ParsingError { error_message }
-    }
+impl LineReader {
+    fn open(path: &str) -> Result<Self, IzeError> {
+        let file = File::open(path).map_err(|e| IzeError::io(path, e))?;
 
-    pub fn box_new(error_message: String) -> Box<Self> {
-        Box::new(ParsingError::new(error_message))
+        Ok(LineReader {
+            path: path.to_string(),
+            lines: BufReader::new(file).lines().peekable(),
+            line_number: 0,
+        })
     }
-}
 
-impl Display for ParsingError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.error_message)
+    fn next_line(&mut self) -> Result<Option<String>, IzeError> {
+        match self.lines.next() {
+            Some(line) => {
+                self.line_number += 1;
+                line.map(Some).map_err(|e| IzeError::io(&self.path, e))
+            }
+            None => Ok(None),
+        }
     }
-}
 
-impl Error for ParsingError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        None
+    /// The next line without consuming it, or `None` at the end of the file.
+    fn peek(&mut self) -> Result<Option<&str>, IzeError> {
+        if let Some(Err(_)) = self.lines.peek() {
+            // Consume the line to report the read error.
+            self.next_line()?;
+        }
+
+        Ok(self
+            .lines
+            .peek()
+            .map(|line| line.as_deref().unwrap_or_default()))
     }
 
-    fn description(&self) -> &str {
-        "description() is deprecated; use Display"
+    /// An error in the line most recently read.
+    fn error(&self, snippet: &str, message: String) -> IzeError {
+        IzeError::parse(&self.path, self.line_number, snippet, message)
     }
 
-    fn cause(&self) -> Option<&dyn Error> {
-        self.source()
+    /// An error for the file ending too early.
+    fn end_error(&self, message: &str) -> IzeError {
+        IzeError::parse(&self.path, self.line_number + 1, "", message.to_string())
     }
 }
 
-fn scan_or_error(
-    reader: &mut Peekable<Lines<BufReader<File>>>,
-    error_msg: &str,
-) -> Result<(), Box<dyn Error>> {
+fn scan_or_error(reader: &mut LineReader, error_msg: &str) -> Result<(), IzeError> {
     if !scan(reader)? {
-        Err(ParsingError::box_new(error_msg.to_string()))
+        Err(reader.end_error(error_msg))
     } else {
         Ok(())
     }
 }
 
-fn scan(reader: &mut Peekable<Lines<BufReader<File>>>) -> Result<bool, Box<dyn Error>> {
+fn scan(reader: &mut LineReader) -> Result<bool, IzeError> {
     // Iterate until we hit a non-empty line
 
     loop {
-        if let Some(line) = reader.peek() {
-            match line.as_ref() {
-                Ok(s) => {
-                    if !s.trim().is_empty() {
-                        return Ok(true);
-                    }
-                }
-                _ => {}
-            }
-        } else {
-            return Ok(false);
+        match reader.peek()? {
+            Some(line) if !line.trim().is_empty() => return Ok(true),
+            Some(_) => _ = reader.next_line()?,
+            None => return Ok(false),
         }
-
-        // Safe to unwrap because we would have returned already if the iteration expired
-        reader.next().unwrap()?;
     }
 }
 
-fn read_id(reader: &mut Peekable<Lines<BufReader<File>>>) -> Result<usize, Box<dyn Error>> {
-    if let Some(line) = reader.next() {
-        let line = line?;
-
+fn read_id(reader: &mut LineReader) -> Result<usize, IzeError> {
+    if let Some(line) = reader.next_line()? {
         if let Ok(id) = line.parse() {
             Ok(id)
         } else {
-            Err(ParsingError::box_new(format!(
-                "Card id {line} must be a number."
-            )))
+            Err(reader.error(&line, format!("Card id {line} must be a number.")))
         }
     } else {
-        Err(ParsingError::box_new(
-            "Deck file invalid. Expected card id.".to_string(),
-        ))
+        Err(reader.end_error("Deck file invalid. Expected card id."))
     }
 }
 
-fn read_text(reader: &mut Peekable<Lines<BufReader<File>>>) -> Result<String, Box<dyn Error>> {
-    if let Some(line) = reader.next() {
-        let line = line?;
+fn read_text(reader: &mut LineReader) -> Result<String, IzeError> {
+    if let Some(line) = reader.next_line()? {
         Ok(line)
     } else {
-        Err(ParsingError::box_new("Expected card content".to_string()))
+        Err(reader.end_error("Expected card content"))
     }
 }
 
-fn read_card(
-    reader: &mut Peekable<Lines<BufReader<File>>>,
-) -> Result<Option<Card>, Box<dyn Error>> {
+fn read_number(reader: &mut LineReader) -> Result<u64, IzeError> {
+    let line = read_text(reader)?;
+    line.trim()
+        .parse()
+        .map_err(|_| reader.error(&line, "Expected a number".to_string()))
+}
+
+fn read_card(reader: &mut LineReader) -> Result<Option<ReadCard>, IzeError> {
     // Done iterating
     if !scan(reader)? {
         return Ok(None);
     }
 
+    let line = reader.line_number + 1;
     let card_id = read_id(reader)?;
     let front = read_text(reader)?;
     let back = read_text(reader)?;
 
-    Ok(Some(Card {
-        front,
-        back,
+    Ok(Some(ReadCard {
+        card_id: Some(card_id),
+        line,
+        card: Card {
+            front,
+            back,
+            card_id,
+            direction: None,
+            tags: vec![],
+        },
+    }))
+}
+
+// Version 2 of the deck format starts with a header line and allows multi-line card content:
+//
+//     #! ize-deck 2
+//     #! title Optional deck title
+//     #! direction forward, reverse or both
+//     #! ids sequential, hash or random, for cards without an id
+//
+//     @id optional, see card_ids
+//     @direction optional, overrides the deck's
+//     @tags optional, separated by spaces
+//     front, one or more lines
+//     ---
+//     back, one or more lines
+//     ===
+//
+// Content lines which start with '\' or '@', or which would be mistaken for a separator, are
+// escaped with a leading '\'.
+const DECK_HEADER: &str = "#! ize-deck";
+const DECK_ATTRIBUTE: &str = "#! ";
+const DECK_FORMAT_VERSION: u32 = 2;
+const FRONT_SEPARATOR: &str = "---";
+const CARD_SEPARATOR: &str = "===";
+
+fn escape_line(line: &str) -> String {
+    if line.starts_with('\\')
+        || line.starts_with('@')
+        || line == FRONT_SEPARATOR
+        || line == CARD_SEPARATOR
+    {
+        format!("\\{line}")
+    } else {
+        line.to_string()
+    }
+}
+
+fn unescape_line(line: String) -> String {
+    match line.strip_prefix('\\') {
+        Some(unescaped) => unescaped.to_string(),
+        None => line,
+    }
+}
+
+/// Read the version from the deck header. Decks without a header are version 1.
+fn read_deck_version(reader: &mut LineReader) -> Result<u32, IzeError> {
+    if !scan(reader)? {
+        return Ok(1);
+    }
+
+    let is_header = matches!(reader.peek()?, Some(line) if line.starts_with(DECK_HEADER));
+    if !is_header {
+        return Ok(1);
+    }
+
+    let header = read_text(reader)?;
+    let version = header[DECK_HEADER.len()..]
+        .trim()
+        .parse()
+        .map_err(|_| reader.error(&header, "Invalid deck header".to_string()))?;
+
+    if version > DECK_FORMAT_VERSION {
+        return Err(reader.error(
+            &header,
+            format!("Deck format version {version} is not supported."),
+        ));
+    }
+
+    Ok(version)
+}
+
+/// Read the `#! key value` lines following the version header.
+fn read_deck_attributes(reader: &mut LineReader, deck: &mut Deck) -> Result<(), IzeError> {
+    while reader
+        .peek()?
+        .is_some_and(|line| line.starts_with(DECK_ATTRIBUTE))
+    {
+        let line = read_text(reader)?;
+        let attribute = &line[DECK_ATTRIBUTE.len()..];
+
+        let (key, value) = attribute.split_once(' ').unwrap_or((attribute, ""));
+        match key {
+            "title" => deck.title = Some(value.trim().to_string()),
+            "direction" => deck.direction = value.parse().map_err(|e| reader.error(&line, e))?,
+            "ids" => deck.id_scheme = value.parse().map_err(|e| reader.error(&line, e))?,
+            _ => return Err(reader.error(&line, format!("Unknown deck attribute '{key}'"))),
+        }
+    }
+
+    Ok(())
+}
+
+/// Read lines up to `separator`. The last card may omit its separator.
+fn read_block(reader: &mut LineReader, separator: &str) -> Result<String, IzeError> {
+    let mut lines = vec![];
+    loop {
+        match reader.next_line()? {
+            Some(line) => {
+                if line == separator {
+                    break;
+                }
+                lines.push(unescape_line(line));
+            }
+            None if separator == CARD_SEPARATOR => break,
+            None => {
+                return Err(reader.end_error(&format!("Deck file invalid. Expected '{separator}'.")))
+            }
+        }
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Cards without an `@id` are given one once the whole deck is read.
+fn read_card_v2(reader: &mut LineReader) -> Result<Option<ReadCard>, IzeError> {
+    if !scan(reader)? {
+        return Ok(None);
+    }
+
+    let start = reader.line_number + 1;
+    let mut card_id = None;
+    let mut direction = None;
+    let mut tags = vec![];
+    while reader.peek()?.is_some_and(|line| line.starts_with('@')) {
+        let line = read_text(reader)?;
+        let attribute = &line[1..];
+
+        let (key, value) = attribute.split_once(' ').unwrap_or((attribute, ""));
+        match key {
+            "id" => {
+                card_id = Some(value.trim().parse().map_err(|_| {
+                    reader.error(&line, format!("Card id {value} must be a number."))
+                })?)
+            }
+            "direction" => direction = Some(value.parse().map_err(|e| reader.error(&line, e))?),
+            "tags" => tags = parse_tags(value),
+            _ => return Err(reader.error(&line, format!("Unknown card attribute '{key}'"))),
+        }
+    }
+
+    let front = read_block(reader, FRONT_SEPARATOR)?;
+    let back = read_block(reader, CARD_SEPARATOR)?;
+
+    Ok(Some(ReadCard {
         card_id,
+        line: start,
+        card: Card {
+            front,
+            back,
+            card_id: 0,
+            direction,
+            tags,
+        },
     }))
 }
 
-pub fn load_deck(deck_path: &str) -> Result<Deck, Box<dyn Error>> {
-    let file = File::open(deck_path)?;
+pub fn load_deck(deck_path: &str) -> Result<Deck, IzeError> {
+    load_deck_with_ids(deck_path, None)
+}
+
+/// Load the deck, giving cards without an id one from `id_scheme` rather than the deck's own
+/// scheme if it is set.
+fn load_deck_with_ids(deck_path: &str, id_scheme: Option<IdScheme>) -> Result<Deck, IzeError> {
+    #[cfg(feature = "anki")]
+    if crate::anki::is_anki_package(deck_path) {
+        return crate::anki::import_deck(deck_path);
+    }
+
+    if is_delimited(deck_path) {
+        let mut options = CsvOptions::for_path(deck_path);
+        options.id_scheme = id_scheme.unwrap_or(options.id_scheme);
+        return load_csv_deck(deck_path, &options);
+    }
+
+    if is_markdown(deck_path) {
+        return load_markdown_deck(deck_path, id_scheme);
+    }
+
+    #[cfg(feature = "serde")]
+    if let Some(format) = DataFormat::for_path(deck_path) {
+        let file: serde_io::DeckFile = serde_io::read(deck_path, format)?;
+        return file
+            .into_deck(id_scheme)
+            .map_err(|e| e.into_error(deck_path));
+    }
+
+    let mut reader = LineReader::open(deck_path)?;
+    let version = read_deck_version(&mut reader)?;
 
-    let mut iter = BufReader::new(file).lines().into_iter().peekable();
+    let mut deck = Deck::new();
+    if version > 1 {
+        read_deck_attributes(&mut reader, &mut deck)?;
+    }
+    deck.id_scheme = id_scheme.unwrap_or(deck.id_scheme);
+
+    let mut cards = vec![];
+    while let Some(card) = match version {
+        1 => read_card(&mut reader)?,
+        _ => read_card_v2(&mut reader)?,
+    } {
+        cards.push(card);
+    }
+    deck.add_read_cards(cards)
+        .map_err(|e| e.into_error(deck_path))?;
+
+    Ok(deck)
+}
+
+/// Give every card of the deck without an id one and write the ids into the deck file, so the
+/// cards keep them however the deck is edited later. `id_scheme` replaces the deck's own scheme,
+/// both for these cards and for cards added later.
+pub fn stamp_ids(deck_path: &str, id_scheme: Option<IdScheme>) -> Result<Deck, IzeError> {
+    let deck = load_deck_with_ids(deck_path, id_scheme)?;
+    save_deck(deck_path, &deck)?;
+
+    Ok(deck)
+}
+
+/// Load the decks of a run over several decks as one, see [`Deck::combine`].
+pub fn load_decks(deck_paths: &[String]) -> Result<Deck, IzeError> {
+    if let Some(path) = deck_paths.get(MAX_DECKS) {
+        return Err(IzeError::format(
+            path,
+            format!("A run can study at most {MAX_DECKS} decks."),
+        ));
+    }
 
-    let mut cards = HashMap::<usize, Card>::new();
-    while {
-        if let Some(card) = read_card(&mut iter)? {
-            // If there are duplicates, onlt the last one will be taken.
-            cards.insert(card.card_id, card);
-            true
+    let decks = deck_paths
+        .iter()
+        .map(|path| load_run_deck(path))
+        .collect::<Result<Vec<Deck>, IzeError>>()?;
+
+    Ok(Deck::combine(decks))
+}
+
+/// Load a deck for a run, which needs every card to have an id written in the file, see
+/// [`stamp_ids`]. The deck itself is never written.
+fn load_run_deck(deck_path: &str) -> Result<Deck, IzeError> {
+    let deck = load_deck(deck_path)?;
+    if deck.generated_ids > 0 {
+        return Err(IzeError::format(
+            deck_path,
+            format!(
+                "{} cards have no id, so a run would lose track of them. Stamp the deck's ids \
+                 to study it.",
+                deck.generated_ids
+            ),
+        ));
+    }
+
+    Ok(deck)
+}
+
+fn is_single_line(text: &str) -> bool {
+    !text.contains(['\n', '\r'])
+}
+
+/// The oldest format version which can store every card without loss.
+fn deck_format_version(deck: &Deck) -> u32 {
+    let single_line = deck
+        .cards
+        .values()
+        .all(|card| is_single_line(&card.front) && is_single_line(&card.back));
+
+    let attributes = deck.direction != Direction::Forward
+        || !deck.id_scheme.is_sequential()
+        || deck
+            .cards
+            .values()
+            .any(|card| card.direction.is_some() || !card.tags.is_empty());
+
+    if single_line && deck.title.is_none() && !attributes {
+        1
+    } else {
+        DECK_FORMAT_VERSION
+    }
+}
+
+fn write_block(w: &mut impl Write, text: &str) -> io::Result<()> {
+    for (i, line) in text.split('\n').enumerate() {
+        // A leading empty line would otherwise be skipped as padding between cards.
+        if i == 0 && line.is_empty() {
+            writeln!(w, "\\")?;
         } else {
-            false
+            writeln!(w, "{}", escape_line(line))?;
         }
-    } {}
+    }
+
+    Ok(())
+}
+
+pub fn save_deck(filepath: &str, deck: &Deck) -> Result<(), IzeError> {
+    save_deck_with_options(filepath, deck, &SaveOptions::default())
+}
 
-    Ok(Deck { cards })
+pub fn save_deck_with_options(
+    filepath: &str,
+    deck: &Deck,
+    options: &SaveOptions,
+) -> Result<(), IzeError> {
+    #[cfg(feature = "anki")]
+    if crate::anki::is_anki_package(filepath) {
+        return Err(IzeError::format(
+            filepath,
+            "Anki packages can only be imported. Save the deck to a new file.",
+        ));
+    }
+
+    if is_delimited(filepath) {
+        return save_csv_deck(filepath, deck, &CsvOptions::for_path(filepath), options);
+    }
+
+    #[cfg(feature = "serde")]
+    if let Some(format) = DataFormat::for_path(filepath) {
+        write_atomically(filepath, options, |w| serde_io::write(w, deck, format))?;
+        return Ok(());
+    }
+
+    if is_markdown(filepath) {
+        write_atomically(filepath, options, |w| write_markdown_deck(w, deck))?;
+    } else {
+        write_atomically(filepath, options, |w| write_deck(w, deck))?;
+    }
+
+    Ok(())
 }
 
-pub fn save_deck(filepath: &str, deck: Deck) -> Result<(), Box<dyn Error>> {
-    //Todo: write atomically...
-    let file = File::create(filepath)?;
-    let mut writer = BufWriter::new(&file);
+fn write_deck(w: &mut impl Write, deck: &Deck) -> io::Result<()> {
+    // Sort to avoid churn in the format of the deck file
+    let mut cards: Vec<&Card> = deck.cards.values().collect();
+    cards.sort_by_key(|card| card.card_id);
 
-    writeln!(&mut writer, "")?;
+    let version = deck_format_version(deck);
+    if version == 1 {
+        writeln!(w)?;
 
-    for (_, card) in deck.cards {
-        writeln!(&mut writer, "{}", card.card_id)?;
-        writeln!(&mut writer, "{}", &card.front)?;
-        writeln!(&mut writer, "{}", &card.back)?;
-        writeln!(&mut writer, "")?;
+        for card in cards {
+            writeln!(w, "{}", card.card_id)?;
+            writeln!(w, "{}", &card.front)?;
+            writeln!(w, "{}", &card.back)?;
+            writeln!(w)?;
+        }
+    } else {
+        writeln!(w, "{} {}", DECK_HEADER, version)?;
+        if let Some(title) = &deck.title {
+            writeln!(
+                w,
+                "{}title {}",
+                DECK_ATTRIBUTE,
+                title.replace(['\n', '\r'], " ")
+            )?;
+        }
+        if deck.direction != Direction::Forward {
+            writeln!(w, "{}direction {}", DECK_ATTRIBUTE, deck.direction)?;
+        }
+        if !deck.id_scheme.is_sequential() {
+            writeln!(w, "{}ids {}", DECK_ATTRIBUTE, deck.id_scheme)?;
+        }
+        writeln!(w)?;
+
+        for card in cards {
+            writeln!(w, "@id {}", card.card_id)?;
+            if let Some(direction) = card.direction {
+                writeln!(w, "@direction {}", direction)?;
+            }
+            if !card.tags.is_empty() {
+                writeln!(w, "@tags {}", card.tags.join(" "))?;
+            }
+            write_block(w, &card.front)?;
+            writeln!(w, "{}", FRONT_SEPARATOR)?;
+            write_block(w, &card.back)?;
+            writeln!(w, "{}", CARD_SEPARATOR)?;
+            writeln!(w)?;
+        }
     }
 
     Ok(())
 }
 
-fn read_id_list(
-    reader: &mut Peekable<Lines<BufReader<File>>>,
-) -> Result<Vec<usize>, Box<dyn Error>> {
-    let mut vec = vec![];
-    loop {
-        if let Some(line) = reader.peek() {
-            match line.as_ref() {
-                Ok(line) => {
-                    if let Ok(parsed) = line.parse() {
-                        vec.push(parsed);
-                        // Safe to unwrap due to the prior peek succeeding.
-                        _ = reader.next().unwrap();
-                    } else {
-                        // return on the first failed parse
-                        return Ok(vec);
-                    }
+// Markdown decks hold one card per level 2 heading, so they read well as documents:
+//
+//     # Optional deck title
+//     <!-- direction: both -->
+//     <!-- ids: hash -->
+//
+//     ## Front of the card
+//     <!-- id: 12 -->
+//     <!-- direction: reverse -->
+//     <!-- tags: verbs irregular -->
+//
+//     Back of the card, one or more lines.
+//
+// The comments setting the id scheme, direction and tags are optional. Card comments have to come
+// straight after the heading.
+//
+// Line breaks in a front are written as `<br>` and blank lines around a back are dropped. Back
+// lines which would be read as a title or card heading are escaped with a leading '\', which
+// Markdown renders invisibly. Text before the first card is ignored, and cards without an id are
+// given one by the deck's id scheme.
+const MARKDOWN_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+const MARKDOWN_TITLE: &str = "#";
+const MARKDOWN_CARD: &str = "##";
+const MARKDOWN_LINE_BREAK: &str = "<br>";
+const MARKDOWN_COMMENT_START: &str = "<!--";
+const MARKDOWN_COMMENT_END: &str = "-->";
+
+fn is_markdown(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            MARKDOWN_EXTENSIONS
+                .iter()
+                .any(|markdown| extension.eq_ignore_ascii_case(markdown))
+        })
+}
+
+/// The text of a heading of exactly `level`, e.g. `## Front` for `MARKDOWN_CARD`.
+fn markdown_heading<'a>(line: &'a str, level: &str) -> Option<&'a str> {
+    let text = line.strip_prefix(level)?;
+    if text.is_empty() || text.starts_with(' ') {
+        Some(text.trim())
+    } else {
+        None
+    }
+}
+
+fn is_markdown_heading(line: &str) -> bool {
+    markdown_heading(line, MARKDOWN_TITLE).is_some()
+        || markdown_heading(line, MARKDOWN_CARD).is_some()
+}
+
+/// True if the line opens or closes a fenced code block, in which headings are plain text.
+fn is_markdown_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// The key and value of a `<!-- key: value -->` line.
+fn markdown_attribute(line: &str) -> Option<(&str, &str)> {
+    let comment = line
+        .trim()
+        .strip_prefix(MARKDOWN_COMMENT_START)?
+        .strip_suffix(MARKDOWN_COMMENT_END)?;
+    let (key, value) = comment.split_once(':')?;
+
+    Some((key.trim(), value.trim()))
+}
+
+fn write_markdown_attribute(w: &mut impl Write, key: &str, value: impl Display) -> io::Result<()> {
+    writeln!(
+        w,
+        "{} {}: {} {}",
+        MARKDOWN_COMMENT_START, key, value, MARKDOWN_COMMENT_END
+    )
+}
+
+/// Lines of the back without the blank lines around them.
+fn markdown_back(lines: &[String]) -> String {
+    let is_blank = |line: &&String| line.trim().is_empty();
+    let start = lines.iter().position(|line| !is_blank(&line));
+    let end = lines.iter().rposition(|line| !is_blank(&line));
+
+    match (start, end) {
+        (Some(start), Some(end)) => lines[start..=end].join("\n"),
+        _ => String::new(),
+    }
+}
+
+struct MarkdownCard {
+    card_id: Option<usize>,
+    line: usize,
+    direction: Option<Direction>,
+    tags: Option<Vec<String>>,
+    front: String,
+    back: Vec<String>,
+}
+
+fn load_markdown_deck(deck_path: &str, id_scheme: Option<IdScheme>) -> Result<Deck, IzeError> {
+    let mut reader = LineReader::open(deck_path)?;
+    let mut deck = Deck::new();
+
+    let mut cards: Vec<MarkdownCard> = vec![];
+    let mut in_fence = false;
+    while let Some(line) = reader.next_line()? {
+        if !in_fence {
+            if let Some(front) = markdown_heading(&line, MARKDOWN_CARD) {
+                cards.push(MarkdownCard {
+                    card_id: None,
+                    line: reader.line_number,
+                    direction: None,
+                    tags: None,
+                    front: front.replace(MARKDOWN_LINE_BREAK, "\n"),
+                    back: vec![],
+                });
+                continue;
+            }
+        }
+
+        let Some(card) = cards.last_mut() else {
+            if !in_fence {
+                if let Some(title) = markdown_heading(&line, MARKDOWN_TITLE) {
+                    deck.title.get_or_insert_with(|| title.to_string());
+                } else if let Some(("direction", value)) = markdown_attribute(&line) {
+                    deck.direction = value.parse().map_err(|e| reader.error(&line, e))?;
+                } else if let Some(("ids", value)) = markdown_attribute(&line) {
+                    deck.id_scheme = value.parse().map_err(|e| reader.error(&line, e))?;
+                }
+            }
+            in_fence ^= is_markdown_fence(&line);
+            continue;
+        };
+
+        // Other comments are part of the back.
+        if card.back.is_empty() {
+            match markdown_attribute(&line) {
+                Some(("id", value)) if card.card_id.is_none() => {
+                    card.card_id = Some(value.parse().map_err(|_| {
+                        reader.error(&line, format!("Card id {value} must be a number."))
+                    })?);
+                    continue;
+                }
+                Some(("direction", value)) if card.direction.is_none() => {
+                    card.direction = Some(value.parse().map_err(|e| reader.error(&line, e))?);
+                    continue;
+                }
+                Some(("tags", value)) if card.tags.is_none() => {
+                    card.tags = Some(parse_tags(value));
+                    continue;
                 }
                 _ => {}
             }
-        } else {
-            // Return on iteration end
-            return Ok(vec);
         }
+
+        let escaped = !in_fence
+            && line.starts_with('\\')
+            && is_markdown_heading(line.trim_start_matches('\\'));
+        in_fence ^= is_markdown_fence(&line);
+        card.back
+            .push(if escaped { line[1..].to_string() } else { line });
     }
+
+    deck.id_scheme = id_scheme.unwrap_or(deck.id_scheme);
+    let cards = cards
+        .into_iter()
+        .map(|card| ReadCard {
+            card_id: card.card_id,
+            line: card.line,
+            card: Card {
+                card_id: 0,
+                front: card.front,
+                back: markdown_back(&card.back),
+                direction: card.direction,
+                tags: card.tags.unwrap_or_default(),
+            },
+        })
+        .collect();
+    deck.add_read_cards(cards)
+        .map_err(|e| e.into_error(deck_path))?;
+
+    Ok(deck)
 }
 
-fn load_practice_run_file(filepath: &str) -> Result<PracticeRun, Box<dyn Error>> {
-    let file = File::open(filepath)?;
-    let mut iter = BufReader::new(file).lines().into_iter().peekable();
-    let reader = &mut iter;
+fn write_markdown_deck(w: &mut impl Write, deck: &Deck) -> io::Result<()> {
+    // Sort to avoid churn in the format of the deck file
+    let mut cards: Vec<&Card> = deck.cards.values().collect();
+    cards.sort_by_key(|card| card.card_id);
+
+    if let Some(title) = &deck.title {
+        writeln!(w, "{} {}", MARKDOWN_TITLE, title.replace(['\n', '\r'], " "))?;
+    }
+    if deck.direction != Direction::Forward {
+        write_markdown_attribute(w, "direction", deck.direction)?;
+    }
+    if !deck.id_scheme.is_sequential() {
+        write_markdown_attribute(w, "ids", deck.id_scheme)?;
+    }
+    if deck.title.is_some()
+        || deck.direction != Direction::Forward
+        || !deck.id_scheme.is_sequential()
+    {
+        writeln!(w)?;
+    }
+
+    for card in cards {
+        let front: Vec<&str> = card.front.lines().map(|line| line.trim()).collect();
+        writeln!(w, "{} {}", MARKDOWN_CARD, front.join(MARKDOWN_LINE_BREAK))?;
+        write_markdown_attribute(w, "id", card.card_id)?;
+        if let Some(direction) = card.direction {
+            write_markdown_attribute(w, "direction", direction)?;
+        }
+        if !card.tags.is_empty() {
+            write_markdown_attribute(w, "tags", card.tags.join(" "))?;
+        }
+        writeln!(w)?;
+
+        if !card.back.is_empty() {
+            let mut in_fence = false;
+            for line in card.back.lines() {
+                if !in_fence && is_markdown_heading(line.trim_start_matches('\\')) {
+                    writeln!(w, "\\{line}")?;
+                } else {
+                    writeln!(w, "{line}")?;
+                }
+                in_fence ^= is_markdown_fence(line);
+            }
+            writeln!(w)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_id_list(reader: &mut LineReader) -> Result<Vec<usize>, IzeError> {
+    let mut vec = vec![];
+    // Stop on the first line which isn't an id, which is the next heading.
+    while let Some(parsed) = reader.peek()?.and_then(parse_item_id) {
+        vec.push(parsed);
+        reader.next_line()?;
+    }
+
+    Ok(vec)
+}
+
+/// Headings of the sections of a run file other than those of the boxes.
+const RUN_HEADINGS: [&str; 12] = [
+    "boxes",
+    "session",
+    "learning steps",
+    "order",
+    "seed",
+    "shuffles",
+    "last graded",
+    "decks",
+    "filter",
+    "relearning",
+    "remaining",
+    "origins",
+];
+
+/// Why `name` can't be given to a box after those named `earlier`, if it can't. The cards of a box
+/// are listed under its name, which is matched ignoring case, so it has to stand apart from the
+/// other headings and from the ids listed under them.
+fn box_name_error<'a>(name: &str, mut earlier: impl Iterator<Item = &'a str>) -> Option<String> {
+    let heading = name.to_lowercase();
+    if heading.trim().is_empty() {
+        Some("Boxes need a name.".to_string())
+    } else if RUN_HEADINGS.contains(&heading.as_str()) {
+        Some(format!(
+            "Box name '{name}' is the heading of another section."
+        ))
+    } else if parse_item_id(name).is_some() {
+        Some(format!("Box name '{name}' would be read as a card id."))
+    } else if earlier.any(|other| other.to_lowercase() == heading) {
+        Some(format!("There is more than one box named '{name}'."))
+    } else {
+        None
+    }
+}
+
+/// Box definitions are written one per line as `name|review frequency|promote to|demote to`.
+fn read_box_list(reader: &mut LineReader) -> Result<Vec<CardBox>, IzeError> {
+    let mut boxes: Vec<CardBox> = vec![];
+    while reader
+        .peek()?
+        .is_some_and(|line| line.split('|').count() == 4)
+    {
+        let line = read_text(reader)?;
+        let fields: Vec<&str> = line.split('|').collect();
+
+        let numbers: Vec<usize> = fields[1..]
+            .iter()
+            .map(|field| field.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| reader.error(&line, "Invalid box definition".to_string()))?;
+        let names = boxes.iter().map(|card_box| card_box.name.as_str());
+        if let Some(message) = box_name_error(fields[0], names) {
+            return Err(reader.error(&line, message));
+        }
+
+        boxes.push(CardBox::new(fields[0], numbers[0], numbers[1], numbers[2]));
+    }
+
+    if boxes.is_empty() {
+        return Err(reader.end_error("Run file invalid. Expected at least one box."));
+    }
+
+    Ok(boxes)
+}
+
+/// Deck paths are written one per line up to the next blank line.
+fn read_path_list(reader: &mut LineReader) -> Result<Vec<String>, IzeError> {
+    let mut paths = vec![];
+    while let Some(line) = reader.next_line()? {
+        if line.trim().is_empty() {
+            break;
+        }
+        if paths.len() + 1 == MAX_DECKS {
+            return Err(reader.error(&line, format!("A run can study at most {MAX_DECKS} decks.")));
+        }
+        paths.push(line);
+    }
+
+    Ok(paths)
+}
+
+fn parse_id_number(line: &str) -> Option<(usize, usize)> {
+    let (id, number) = line.split_once(' ')?;
+    Some((parse_item_id(id)?, number.trim().parse().ok()?))
+}
+
+/// Origins and relearning steps are written one per line as `item id number`, the number being
+/// the box index or the step.
+fn read_id_map(reader: &mut LineReader) -> Result<HashMap<usize, usize>, IzeError> {
+    let mut map = HashMap::new();
+    while let Some((id, number)) = reader.peek()?.and_then(parse_id_number) {
+        map.insert(id, number);
+        reader.next_line()?;
+    }
+
+    Ok(map)
+}
+
+fn load_practice_run_file(filepath: &str) -> Result<PracticeRun, IzeError> {
+    let last_save = fs::canonicalize(filepath)
+        .map_err(|e| IzeError::io(filepath, e))?
+        .to_string_lossy()
+        .to_string();
+
+    #[cfg(feature = "serde")]
+    if let Some(format) = DataFormat::for_path(filepath) {
+        let mut run: PracticeRun = serde_io::read(filepath, format)?;
+        run.last_save = last_save;
+        clamp_box_indices(&mut run);
+        return Ok(run);
+    }
+
+    let mut reader = LineReader::open(filepath)?;
+    let reader = &mut reader;
 
     let mut run = PracticeRun::new();
-    run.last_save = fs::canonicalize(filepath)?.to_str().unwrap().to_string();
+    run.last_save = last_save;
 
     scan_or_error(reader, "Expected deck file path.")?;
 
     run.deck_path = read_text(reader)?;
 
+    let schedule_path = schedule_path(filepath);
+    if Path::new(&schedule_path).exists() {
+        run.schedule = load_schedule(&schedule_path)?;
+    }
+
+    // Runs saved without box definitions use the default piles.
     while scan(reader)? {
-        let header = read_text(reader)?.to_lowercase();
+        let line = read_text(reader)?;
+        let header = line.to_lowercase();
         if header == "remaining" {
             run.remaining = read_id_list(reader)?;
-        } else if header == "working" {
-            run.working = read_id_list(reader)?;
-        } else if header == "incorrect" {
-            run.incorrect = read_id_list(reader)?;
-        } else if header == "memorized" {
-            run.memorized = read_id_list(reader)?;
+        } else if header == "boxes" {
+            run.boxes = read_box_list(reader)?;
+        } else if header == "session" {
+            run.session = read_id_list(reader)?.first().copied().unwrap_or(0);
+        } else if header == "origins" {
+            run.origins = read_id_map(reader)?;
+        } else if header == "relearning" {
+            run.relearning = read_id_map(reader)?;
+        } else if header == "decks" {
+            run.other_deck_paths = read_path_list(reader)?;
+        } else if header == "filter" {
+            let filter = read_text(reader)?;
+            run.filter = filter.parse().map_err(|e| reader.error(&filter, e))?;
+        } else if header == "learning steps" {
+            let steps = read_text(reader)?;
+            run.learning_steps =
+                parse_learning_steps(&steps).map_err(|e| reader.error(&steps, e))?;
+        } else if header == "order" {
+            let order = read_text(reader)?;
+            run.order = order.parse().map_err(|e| reader.error(&order, e))?;
+        } else if header == "seed" {
+            run.seed = read_number(reader)?;
+        } else if header == "shuffles" {
+            run.shuffles = read_number(reader)?;
+        } else if header == "last graded" {
+            run.last_graded = read_id_list(reader)?.first().copied();
+        } else if let Some(card_box) = run
+            .boxes
+            .iter_mut()
+            .find(|card_box| card_box.name.to_lowercase() == header)
+        {
+            card_box.ids = read_id_list(reader)?;
         } else {
-            return Err(ParsingError::box_new(format!(
-                "Unexpected heading {}",
-                header
-            )));
+            return Err(reader.error(&line, format!("Unexpected heading {}", header)));
         }
     }
 
+    clamp_box_indices(&mut run);
+
     Ok(run)
 }
 
-fn check_duplicates(run: &PracticeRun) -> Result<HashSet<usize>, Box<dyn Error>> {
-    let mut set = HashSet::<usize>::new();
+/// Drop references to boxes which the run doesn't have.
+fn clamp_box_indices(run: &mut PracticeRun) {
+    if run.boxes.is_empty() {
+        run.boxes = BoxLayout::Piles.boxes();
+    }
 
-    let id_lists = [&run.remaining, &run.incorrect, &run.memorized, &run.working];
+    let box_count = run.boxes.len();
+    run.origins.retain(|_, index| *index < box_count);
+    for card_box in run.boxes.iter_mut() {
+        card_box.promote_to = card_box.promote_to.min(box_count - 1);
+        card_box.demote_to = card_box.demote_to.min(box_count - 1);
+    }
+}
+
+fn id_lists(run: &PracticeRun) -> impl Iterator<Item = &Vec<usize>> {
+    std::iter::once(&run.remaining).chain(run.boxes.iter().map(|card_box| &card_box.ids))
+}
+
+fn check_duplicates(filepath: &str, run: &PracticeRun) -> Result<HashSet<usize>, IzeError> {
+    let mut set = HashSet::<usize>::new();
 
-    for v in id_lists {
+    for v in id_lists(run) {
         for id in v.iter() {
             if !set.insert(*id) {
-                return Err(ParsingError::box_new(format!(
-                    "Run file invalid: id {} found in multiple locations.",
-                    id
-                )));
+                return Err(IzeError::RunInconsistent {
+                    path: PathBuf::from(filepath),
+                    message: format!("id {} found in multiple locations.", id),
+                });
             }
         }
     }
@@ -238,12 +980,8 @@ fn check_duplicates(run: &PracticeRun) -> Result<HashSet<usize>, Box<dyn Error>>
 }
 
 fn remove_id(run: &mut PracticeRun, id: usize) {
-    let id_lists = [
-        &mut run.remaining,
-        &mut run.incorrect,
-        &mut run.memorized,
-        &mut run.working,
-    ];
+    let id_lists = std::iter::once(&mut run.remaining)
+        .chain(run.boxes.iter_mut().map(|card_box| &mut card_box.ids));
 
     for list in id_lists {
         if let Some(index) = list.iter().position(|_id| *_id == id) {
@@ -253,74 +991,497 @@ fn remove_id(run: &mut PracticeRun, id: usize) {
     }
 }
 
-pub fn load_practice_run(filepath: &str) -> Result<(PracticeRun, Deck), Box<dyn Error>> {
+/// Load the run along with its decks. A run over several decks gets them combined into one, see
+/// [`Deck::combine`].
+pub fn load_practice_run(filepath: &str) -> Result<(PracticeRun, Deck), IzeError> {
+    let run = load_practice_run_file(filepath)?;
+
+    reconcile_with_deck(filepath, run)
+}
+
+/// Load a run whose deck has moved to `deck_path`, e.g. after [`IzeError::MissingDeck`]. In a run
+/// over several decks this replaces the first deck which can't be found.
+pub fn load_practice_run_with_deck(
+    filepath: &str,
+    deck_path: &str,
+) -> Result<(PracticeRun, Deck), IzeError> {
     let mut run = load_practice_run_file(filepath)?;
 
-    let run_ids = check_duplicates(&run)?;
+    let mut paths: Vec<String> = run
+        .deck_paths()
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+    let missing = paths
+        .iter()
+        .position(|path| !Path::new(path).exists())
+        .unwrap_or(0);
+    paths[missing] = deck_path.to_string();
+    run.set_deck_paths(&paths);
+
+    reconcile_with_deck(filepath, run)
+}
+
+/// Bring the run up to date with each of its decks: cards added to a deck join the run and cards
+/// deleted from it leave.
+fn reconcile_with_deck(
+    filepath: &str,
+    mut run: PracticeRun,
+) -> Result<(PracticeRun, Deck), IzeError> {
+    let run_ids = check_duplicates(filepath, &run)?;
 
-    let deck = load_deck(&run.deck_path)?;
+    let deck_paths: Vec<String> = run
+        .deck_paths()
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+    let deck = load_decks(&deck_paths).map_err(|error| match error {
+        IzeError::Io { path, source } if source.kind() == io::ErrorKind::NotFound => {
+            IzeError::MissingDeck {
+                run_path: PathBuf::from(filepath),
+                deck_path: path,
+                source,
+            }
+        }
+        // Decks which exist but can't be read are reported as they are.
+        error => error,
+    })?;
 
     // Add any newly added cards into the run set.
-    for (_, card) in deck.cards.iter() {
-        if !run_ids.contains(&card.card_id) {
-            run.remaining.push(card.card_id);
+    for id in deck.filtered_item_ids(&run.filter) {
+        if !run_ids.contains(&id) {
+            run.remaining.push(id);
         }
     }
 
+    // Cards whose tags no longer pass the filter leave the run as if they had been deleted.
     for id in run_ids.iter() {
-        if !deck.cards.contains_key(id) {
+        let in_run = deck
+            .item(*id)
+            .is_some_and(|item| run.filter.matches(item.card));
+        if !in_run {
             remove_id(&mut run, *id);
+            run.origins.remove(id);
+            run.schedule.remove(*id);
         }
     }
+    run.relearning.retain(|id, _| run.remaining.contains(id));
 
     Ok((run, deck))
 }
 
-fn write_ids(w: &mut impl Write, ids: &[usize]) -> Result<(), Box<dyn Error>> {
+fn write_ids(w: &mut impl Write, ids: &[usize]) -> io::Result<()> {
     for id in ids.iter() {
-        writeln!(w, "{}", id)?;
+        writeln!(w, "{}", format_item_id(*id))?;
     }
 
     Ok(())
 }
 
-pub fn save_practice_run(filepath: &str, run: &PracticeRun) -> Result<(), Box<dyn Error>> {
-    //Todo: write atomically...
-    let file = File::create(filepath)?;
-    let mut writer = BufWriter::new(&file);
-    let w = &mut writer;
+fn write_id_map(w: &mut impl Write, map: &HashMap<usize, usize>) -> io::Result<()> {
+    let mut entries: Vec<(&usize, &usize)> = map.iter().collect();
+    entries.sort();
 
-    // Sort to avoid churn in the format of the run file
-    let mut remaining = run.remaining.clone();
-    remaining.sort();
+    for (id, number) in entries {
+        writeln!(w, "{} {}", format_item_id(*id), number)?;
+    }
 
-    let mut working = run.working.clone();
-    working.sort();
+    Ok(())
+}
 
-    let mut incorrect = run.working.clone();
-    incorrect.sort();
+pub fn save_practice_run(filepath: &str, run: &mut PracticeRun) -> Result<(), IzeError> {
+    save_practice_run_with_options(filepath, run, &SaveOptions::default())
+}
 
-    let mut memorized = run.working.clone();
-    memorized.sort();
+/// Save the run, after which it counts as loaded from `filepath` and keeps its review log there.
+pub fn save_practice_run_with_options(
+    filepath: &str,
+    run: &mut PracticeRun,
+    options: &SaveOptions,
+) -> Result<(), IzeError> {
+    write_practice_run_files(filepath, run, options)?;
+    run.saved_to(filepath)
+}
 
-    writeln!(w, "")?;
+fn write_practice_run_files(
+    filepath: &str,
+    run: &PracticeRun,
+    options: &SaveOptions,
+) -> Result<(), IzeError> {
+    // Serialized runs hold their schedule.
+    #[cfg(feature = "serde")]
+    if let Some(format) = DataFormat::for_path(filepath) {
+        write_atomically(filepath, options, |w| serde_io::write(w, run, format))?;
+        return Ok(());
+    }
+
+    let names: Vec<String> = run
+        .boxes
+        .iter()
+        .map(|card_box| card_box.name.replace('|', "/"))
+        .collect();
+    for (index, name) in names.iter().enumerate() {
+        if let Some(message) = box_name_error(name, names[..index].iter().map(String::as_str)) {
+            return Err(IzeError::format(filepath, message));
+        }
+    }
+
+    write_atomically(filepath, options, |w| write_practice_run(w, run))?;
+    write_atomically(&schedule_path(filepath), options, |w| {
+        write_schedule(w, &run.schedule)
+    })?;
+
+    Ok(())
+}
+
+fn write_practice_run(w: &mut impl Write, run: &PracticeRun) -> io::Result<()> {
+    writeln!(w)?;
 
     writeln!(w, "{}", run.deck_path)?;
-    writeln!(w, "")?;
+    writeln!(w)?;
+
+    writeln!(w, "boxes")?;
+    for card_box in run.boxes.iter() {
+        writeln!(
+            w,
+            "{}|{}|{}|{}",
+            card_box.name.replace('|', "/"),
+            card_box.review_frequency,
+            card_box.promote_to,
+            card_box.demote_to
+        )?;
+    }
+    writeln!(w)?;
+
+    writeln!(w, "session")?;
+    writeln!(w, "{}", run.session)?;
+    writeln!(w)?;
+
+    writeln!(w, "learning steps")?;
+    writeln!(w, "{}", format_learning_steps(&run.learning_steps))?;
+    writeln!(w)?;
 
+    if !run.order.is_uniform() {
+        writeln!(w, "order")?;
+        writeln!(w, "{}", run.order)?;
+        writeln!(w)?;
+    }
+
+    writeln!(w, "seed")?;
+    writeln!(w, "{}", run.seed)?;
+    writeln!(w)?;
+
+    writeln!(w, "shuffles")?;
+    writeln!(w, "{}", run.shuffles)?;
+    writeln!(w)?;
+
+    if let Some(id) = run.last_graded {
+        writeln!(w, "last graded")?;
+        writeln!(w, "{}", format_item_id(id))?;
+        writeln!(w)?;
+    }
+
+    if !run.other_deck_paths.is_empty() {
+        writeln!(w, "decks")?;
+        for path in run.other_deck_paths.iter() {
+            writeln!(w, "{}", path)?;
+        }
+        writeln!(w)?;
+    }
+
+    if !run.filter.is_empty() {
+        writeln!(w, "filter")?;
+        writeln!(w, "{}", run.filter)?;
+        writeln!(w)?;
+    }
+
+    if !run.relearning.is_empty() {
+        writeln!(w, "relearning")?;
+        write_id_map(w, &run.relearning)?;
+        writeln!(w)?;
+    }
+
+    // The remaining pile keeps its order so the pass goes on as it was. Boxes are shuffled before
+    // their cards are shown, so they are sorted to avoid churn in the format of the run file.
     writeln!(w, "remaining")?;
-    write_ids(w, &remaining)?;
+    write_ids(w, &run.remaining)?;
+
+    for card_box in run.boxes.iter() {
+        let mut ids = card_box.ids.clone();
+        ids.sort();
+
+        writeln!(w, "{}", card_box.name.replace('|', "/"))?;
+        write_ids(w, &ids)?;
+    }
+
+    writeln!(w, "origins")?;
+    write_id_map(w, &run.origins)?;
+
+    writeln!(w)?;
+
+    Ok(())
+}
 
-    writeln!(w, "working")?;
-    write_ids(w, &working)?;
+/// The schedule is kept next to the run file so that the run format stays unchanged.
+fn schedule_path(run_path: &str) -> String {
+    format!("{run_path}.schedule")
+}
+
+fn load_schedule(filepath: &str) -> Result<Schedule, IzeError> {
+    let mut reader = LineReader::open(filepath)?;
+    let reader = &mut reader;
+
+    scan_or_error(reader, "Expected scheduler.")?;
+    let header = read_text(reader)?;
+    let scheduler = parse_scheduler(&header).map_err(|e| reader.error(&header, e.to_string()))?;
+    let mut schedule = Schedule::new(scheduler);
 
-    writeln!(w, "incorrect")?;
-    write_ids(w, &incorrect)?;
+    while let Some(line) = reader.next_line()? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (id, fields) = line.split_once(' ').unwrap_or((&line, ""));
+        let id = parse_item_id(id)
+            .ok_or_else(|| reader.error(&line, format!("Invalid schedule card id {id}.")))?;
+        let card = fields
+            .parse::<CardSchedule>()
+            .map_err(|e| reader.error(&line, e.to_string()))?;
 
-    writeln!(w, "memorized")?;
-    write_ids(w, &memorized)?;
+        schedule.cards.insert(id, card);
+    }
 
-    writeln!(w, "")?;
+    Ok(schedule)
+}
+
+fn write_schedule(w: &mut impl Write, schedule: &Schedule) -> io::Result<()> {
+    writeln!(w, "{}", schedule.scheduler.header())?;
+
+    let mut ids: Vec<&usize> = schedule.cards.keys().collect();
+    ids.sort();
+
+    for id in ids {
+        writeln!(w, "{} {}", format_item_id(*id), schedule.cards[id])?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_actions::RunCategory, sm2::Sm2};
+
+    /// An empty directory for the test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ize-deck-io-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn path_in(dir: &Path, file: &str) -> String {
+        dir.join(file).to_string_lossy().to_string()
+    }
+
+    fn card(card_id: usize, front: &str, back: &str) -> Card {
+        Card {
+            card_id,
+            front: front.to_string(),
+            back: back.to_string(),
+            direction: None,
+            tags: vec![],
+        }
+    }
+
+    fn sides(deck: &Deck) -> Vec<(usize, String, String)> {
+        let mut sides: Vec<(usize, String, String)> = deck
+            .cards
+            .values()
+            .map(|card| (card.card_id, card.front.clone(), card.back.clone()))
+            .collect();
+        sides.sort();
+        sides
+    }
+
+    #[test]
+    fn multi_line_cards_round_trip() {
+        let dir = test_dir("multi-line");
+        let path = path_in(&dir, "deck.txt");
+        let mut deck = Deck::new();
+        for card in [
+            card(1, "go\nwent", "gone"),
+            card(2, "@not an attribute", "---\n===\n\\backslash"),
+            card(3, "walk", "walked"),
+        ] {
+            deck.cards.insert(card.card_id, card);
+        }
+
+        save_deck(&path, &deck).unwrap();
+        let loaded = load_deck(&path).unwrap();
+
+        assert!(fs::read_to_string(&path).unwrap().starts_with(DECK_HEADER));
+        assert_eq!(sides(&loaded), sides(&deck));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn single_line_decks_are_written_as_version_1() {
+        let dir = test_dir("version-1");
+        let path = path_in(&dir, "deck.txt");
+        fs::write(&path, "1\ngo\nwent\n\n2\nwalk\nwalked\n").unwrap();
+
+        let deck = load_deck(&path).unwrap();
+        save_deck(&path, &deck).unwrap();
+
+        assert_eq!(
+            sides(&deck),
+            vec![
+                (1, "go".to_string(), "went".to_string()),
+                (2, "walk".to_string(), "walked".to_string()),
+            ]
+        );
+        assert!(!fs::read_to_string(&path).unwrap().starts_with(DECK_HEADER));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn newer_deck_versions_are_an_error() {
+        let dir = test_dir("version-3");
+        let path = path_in(&dir, "deck.txt");
+        fs::write(&path, "#! ize-deck 3\n").unwrap();
+
+        let result = load_deck(&path);
+
+        assert!(matches!(result, Err(IzeError::Parse { line: 1, .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn markdown_decks_keep_titles_attributes_and_headings_in_backs() {
+        let dir = test_dir("markdown");
+        let path = path_in(&dir, "deck.md");
+        let mut deck = Deck::new();
+        deck.title = Some("Verbs".to_string());
+        deck.direction = Direction::Both;
+        let mut go = card(1, "go\nwent", "## not a card\n\n```\n# code\n```");
+        go.tags = vec!["irregular".to_string(), "verbs".to_string()];
+        let mut walk = card(2, "walk", "walked");
+        walk.direction = Some(Direction::Forward);
+        for card in [go, walk] {
+            deck.cards.insert(card.card_id, card);
+        }
+
+        save_deck(&path, &deck).unwrap();
+        let loaded = load_deck(&path).unwrap();
+
+        assert_eq!(loaded.title, deck.title);
+        assert_eq!(loaded.direction, deck.direction);
+        assert_eq!(sides(&loaded), sides(&deck));
+        assert_eq!(loaded.cards[&1].tags, deck.cards[&1].tags);
+        assert_eq!(loaded.cards[&2].direction, Some(Direction::Forward));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// A run over three cards, saved next to its deck, with the last card graded into the first
+    /// box.
+    fn graded_run(dir: &Path) -> PracticeRun {
+        let deck_path = path_in(dir, "deck.txt");
+        let mut deck = Deck::new();
+        for card in [
+            card(1, "go", "went"),
+            card(2, "walk", "walked"),
+            card(3, "say", "said"),
+        ] {
+            deck.cards.insert(card.card_id, card);
+        }
+        save_deck(&deck_path, &deck).unwrap();
+
+        let mut run =
+            PracticeRun::new_from_deck(&deck, Box::new(Sm2), BoxLayout::Leitner(3).boxes())
+                .with_seed(42);
+        run.set_deck_paths(&[deck_path]);
+        run.move_last(RunCategory::Remaining, RunCategory::Box(0))
+            .unwrap();
+        run
+    }
+
+    #[test]
+    fn text_runs_keep_their_seed_and_draws() {
+        let dir = test_dir("run-seed");
+        let path = path_in(&dir, "study.run");
+        let mut run = graded_run(&dir);
+        assert!(run.shuffles > 0);
+        assert!(run.last_graded.is_some());
+
+        save_practice_run(&path, &mut run).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let (mut loaded, _) = load_practice_run(&path).unwrap();
+
+        assert_eq!(loaded.seed(), run.seed());
+        assert_eq!(loaded.shuffles, run.shuffles);
+        assert_eq!(loaded.remaining, run.remaining);
+        assert_eq!(loaded.last_graded, run.last_graded);
+        // Nothing is lost or added in between, so saving again writes the same file.
+        save_practice_run(&path, &mut loaded).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), saved);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn text_runs_keep_cards_being_relearned() {
+        let dir = test_dir("run-relearning");
+        let path = path_in(&dir, "study.run");
+        let mut run = graded_run(&dir);
+        assert_eq!(run.relearning_count(), 1);
+
+        save_practice_run(&path, &mut run).unwrap();
+        let (loaded, _) = load_practice_run(&path).unwrap();
+
+        assert_eq!(loaded.relearning, run.relearning);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_decks_which_are_gone_are_missing() {
+        let dir = test_dir("run-missing-deck");
+        let path = path_in(&dir, "study.run");
+        let mut run = graded_run(&dir);
+        save_practice_run(&path, &mut run).unwrap();
+
+        fs::remove_file(path_in(&dir, "deck.txt")).unwrap();
+        let missing = load_practice_run(&path);
+        // A directory in place of the deck exists, but can't be read as one.
+        fs::create_dir(path_in(&dir, "deck.txt")).unwrap();
+        let unreadable = load_practice_run(&path);
+
+        assert!(matches!(missing, Err(IzeError::MissingDeck { .. })));
+        assert!(matches!(unreadable, Err(IzeError::Io { .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn boxes_named_like_other_sections_are_an_error() {
+        let dir = test_dir("run-box-names");
+        let path = path_in(&dir, "study.run");
+        let mut run = graded_run(&dir);
+
+        for name in ["Seed", "last graded", "12", "Box 1", ""] {
+            run.boxes[1].name = name.to_string();
+            let result = save_practice_run(&path, &mut run);
+
+            assert!(matches!(result, Err(IzeError::Format { .. })), "{name}");
+        }
+        assert!(!Path::new(&path).exists());
+
+        run.boxes[1].name = "Seed".to_string();
+        // Written by hand, as saving refuses to.
+        let mut text = vec![];
+        write_practice_run(&mut text, &run).unwrap();
+        fs::write(&path, text).unwrap();
+        let result = load_practice_run(&path);
+
+        assert!(matches!(result, Err(IzeError::Parse { .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
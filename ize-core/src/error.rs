@@ -0,0 +1,156 @@
+use std::{error::Error, fmt::Display, io, path::PathBuf};
+
+use crate::atomic_write::SaveError;
+
+/// The one based line and column of byte `offset` of `text`, counting the column in characters.
+pub(crate) fn line_and_column(text: &[u8], offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    let line = before.iter().filter(|byte| **byte == b'\n').count() + 1;
+    let column = String::from_utf8_lossy(&before[line_start..])
+        .chars()
+        .count()
+        + 1;
+
+    (line, column)
+}
+
+/// Errors returned when reading or writing decks, runs and their sibling files.
+#[derive(Debug)]
+pub enum IzeError {
+    /// The file could not be opened, read or appended to.
+    Io { path: PathBuf, source: io::Error },
+    /// The file was read but its contents are invalid.
+    Parse {
+        path: PathBuf,
+        /// One based line number. Points one past the last line if the file ended too early.
+        line: usize,
+        /// One based column in characters, or 0 if the parser doesn't say.
+        column: usize,
+        /// The offending line, empty if the file ended too early.
+        snippet: String,
+        message: String,
+    },
+    /// The file is in a format which could not be imported or written, e.g. a corrupt archive.
+    Format { path: PathBuf, message: String },
+    /// A run refers to a deck which could not be found.
+    MissingDeck {
+        run_path: PathBuf,
+        deck_path: PathBuf,
+        source: io::Error,
+    },
+    /// A run file which parsed but contradicts itself, e.g. a card in two boxes.
+    RunInconsistent { path: PathBuf, message: String },
+    /// Saving failed part way; the previous file is still intact.
+    Save(SaveError),
+}
+
+impl IzeError {
+    pub(crate) fn io(path: &str, source: io::Error) -> Self {
+        IzeError::Io {
+            path: PathBuf::from(path),
+            source,
+        }
+    }
+
+    pub(crate) fn parse(path: &str, line: usize, snippet: &str, message: String) -> Self {
+        IzeError::Parse {
+            path: PathBuf::from(path),
+            line,
+            column: 0,
+            snippet: snippet.to_string(),
+            message,
+        }
+    }
+
+    /// The parse error pointing at `column` of its line.
+    pub(crate) fn at_column(mut self, at: usize) -> Self {
+        if let IzeError::Parse { column, .. } = &mut self {
+            *column = at;
+        }
+        self
+    }
+
+    pub(crate) fn format(path: &str, message: impl Display) -> Self {
+        IzeError::Format {
+            path: PathBuf::from(path),
+            message: message.to_string(),
+        }
+    }
+
+    /// True if the error means the file does not exist, as opposed to being unreadable or invalid.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            IzeError::Io { source, .. } | IzeError::MissingDeck { source, .. } => {
+                source.kind() == io::ErrorKind::NotFound
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Display for IzeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IzeError::Io { path, source } => {
+                write!(f, "Could not access '{}': {}", path.display(), source)
+            }
+            IzeError::Parse {
+                path,
+                line,
+                column,
+                snippet,
+                message,
+            } => {
+                write!(f, "{}:{}:", path.display(), line)?;
+                if *column > 0 {
+                    write!(f, "{}:", column)?;
+                }
+                write!(f, " {}", message)?;
+                if !snippet.is_empty() {
+                    write!(f, "\n    {}", snippet)?;
+                }
+                Ok(())
+            }
+            IzeError::Format { path, message } => {
+                write!(f, "Could not convert '{}': {}", path.display(), message)
+            }
+            IzeError::MissingDeck {
+                run_path,
+                deck_path,
+                source,
+            } => write!(
+                f,
+                "The run '{}' uses the deck '{}' which could not be opened: {}",
+                run_path.display(),
+                deck_path.display(),
+                source
+            ),
+            IzeError::RunInconsistent { path, message } => {
+                write!(f, "Run file '{}' is invalid: {}", path.display(), message)
+            }
+            IzeError::Save(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for IzeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            IzeError::Io { source, .. } | IzeError::MissingDeck { source, .. } => Some(source),
+            IzeError::Save(error) => Some(error),
+            IzeError::Parse { .. } | IzeError::Format { .. } | IzeError::RunInconsistent { .. } => {
+                None
+            }
+        }
+    }
+}
+
+impl From<SaveError> for IzeError {
+    fn from(error: SaveError) -> Self {
+        IzeError::Save(error)
+    }
+}
@@ -0,0 +1,353 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crate::scheduler::{CardSchedule, Grade, Review, ScheduleParseError, Scheduler};
+
+const DECAY: f64 = -0.5;
+const FACTOR: f64 = 19.0 / 81.0;
+
+const WEIGHT_COUNT: usize = 17;
+
+pub const DEFAULT_WEIGHTS: [f64; WEIGHT_COUNT] = [
+    0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461, 2.1072,
+    0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+];
+
+pub const DEFAULT_RETENTION: f64 = 0.9;
+
+/// Range each weight is kept within while fitting.
+const WEIGHT_BOUNDS: [(f64, f64); WEIGHT_COUNT] = [
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (1.0, 10.0),
+    (0.1, 5.0),
+    (0.1, 5.0),
+    (0.0, 0.75),
+    (0.0, 4.5),
+    (0.0, 0.8),
+    (0.01, 3.5),
+    (0.1, 5.0),
+    (0.01, 0.25),
+    (0.01, 0.9),
+    (0.01, 4.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+];
+
+const FIT_ROUNDS: usize = 10;
+
+/// A stability/difficulty memory model after the Free Spaced Repetition Scheduler (FSRS 4.5).
+///
+/// Cards are scheduled for the day on which their recall probability is predicted to drop to
+/// `desired_retention`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fsrs {
+    pub weights: [f64; WEIGHT_COUNT],
+    pub desired_retention: f64,
+}
+
+impl Default for Fsrs {
+    fn default() -> Self {
+        Fsrs {
+            weights: DEFAULT_WEIGHTS,
+            desired_retention: DEFAULT_RETENTION,
+        }
+    }
+}
+
+fn rating(grade: Grade) -> f64 {
+    match grade {
+        Grade::Again => 1.0,
+        Grade::Hard => 2.0,
+        Grade::Good => 3.0,
+        Grade::Easy => 4.0,
+    }
+}
+
+/// Probability of recalling a card `elapsed_days` after it was last reviewed.
+pub fn recall_probability(elapsed_days: f64, stability: f64) -> f64 {
+    (1.0 + FACTOR * elapsed_days / stability).powf(DECAY)
+}
+
+impl Fsrs {
+    pub fn new(desired_retention: f64) -> Self {
+        Fsrs {
+            desired_retention,
+            ..Default::default()
+        }
+    }
+
+    fn initial_stability(&self, grade: Grade) -> f64 {
+        self.weights[rating(grade) as usize - 1].max(0.1)
+    }
+
+    fn initial_difficulty(&self, grade: Grade) -> f64 {
+        let w = &self.weights;
+        (w[4] - (rating(grade) - 3.0) * w[5]).clamp(1.0, 10.0)
+    }
+
+    fn next_difficulty(&self, difficulty: f64, grade: Grade) -> f64 {
+        let w = &self.weights;
+        let difficulty = difficulty - w[6] * (rating(grade) - 3.0);
+        // Mean reversion towards the difficulty of a card first answered "Good".
+        (w[7] * self.initial_difficulty(Grade::Good) + (1.0 - w[7]) * difficulty).clamp(1.0, 10.0)
+    }
+
+    fn recall_stability(&self, difficulty: f64, stability: f64, recall: f64, grade: Grade) -> f64 {
+        let w = &self.weights;
+        let hard_penalty = if grade == Grade::Hard { w[15] } else { 1.0 };
+        let easy_bonus = if grade == Grade::Easy { w[16] } else { 1.0 };
+
+        stability
+            * (1.0
+                + w[8].exp()
+                    * (11.0 - difficulty)
+                    * stability.powf(-w[9])
+                    * ((w[10] * (1.0 - recall)).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus)
+    }
+
+    fn forget_stability(&self, difficulty: f64, stability: f64, recall: f64) -> f64 {
+        let w = &self.weights;
+        let forget = w[11]
+            * difficulty.powf(-w[12])
+            * ((stability + 1.0).powf(w[13]) - 1.0)
+            * (w[14] * (1.0 - recall)).exp();
+
+        forget.min(stability)
+    }
+
+    /// Days until recall probability falls to the desired retention.
+    pub fn next_interval(&self, stability: f64) -> u64 {
+        let interval = stability / FACTOR * (self.desired_retention.powf(1.0 / DECAY) - 1.0);
+        interval.round().max(1.0) as u64
+    }
+
+    /// Update a card's stability and difficulty without touching its due date.
+    fn update_memory(&self, card: &mut CardSchedule, grade: Grade, today: u64) {
+        if card.is_new() {
+            card.stability = self.initial_stability(grade);
+            card.difficulty = self.initial_difficulty(grade);
+        } else {
+            let recall = recall_probability(card.elapsed_days(today) as f64, card.stability);
+            card.stability = if grade.is_success() {
+                self.recall_stability(card.difficulty, card.stability, recall, grade)
+            } else {
+                self.forget_stability(card.difficulty, card.stability, recall)
+            }
+            .max(0.1);
+            card.difficulty = self.next_difficulty(card.difficulty, grade);
+        }
+
+        card.last_review = Some(today);
+    }
+
+    /// Average log loss of predicted recall against the outcome of each review.
+    fn loss(&self, histories: &[Vec<Review>]) -> f64 {
+        let mut loss = 0.0;
+        let mut count = 0;
+
+        for history in histories {
+            let mut card = CardSchedule::default();
+            for review in history {
+                if !card.is_new() {
+                    let recall =
+                        recall_probability(card.elapsed_days(review.day) as f64, card.stability)
+                            .clamp(0.0001, 0.9999);
+
+                    loss -= if review.grade.is_success() {
+                        recall.ln()
+                    } else {
+                        (1.0 - recall).ln()
+                    };
+                    count += 1;
+                }
+
+                self.update_memory(&mut card, review.grade, review.day);
+            }
+        }
+
+        if count == 0 {
+            0.0
+        } else {
+            loss / count as f64
+        }
+    }
+}
+
+/// Group reviews by card in chronological order, keeping only the first review of each day.
+fn review_histories(reviews: &[Review]) -> Vec<Vec<Review>> {
+    let mut histories = HashMap::<usize, Vec<Review>>::new();
+    for review in reviews {
+        histories.entry(review.card_id).or_default().push(*review);
+    }
+
+    histories
+        .into_values()
+        .map(|mut history| {
+            history.sort_by_key(|review| review.day);
+            history.dedup_by_key(|review| review.day);
+            history
+        })
+        .filter(|history| history.len() > 1)
+        .collect()
+}
+
+impl Scheduler for Fsrs {
+    fn review(&self, card: &mut CardSchedule, grade: Grade, today: u64) {
+        if grade.is_success() {
+            card.repetitions += 1;
+        } else {
+            card.repetitions = 0;
+            card.lapses += 1;
+        }
+
+        self.update_memory(card, grade, today);
+
+        card.interval = if grade.is_success() {
+            self.next_interval(card.stability)
+        } else {
+            1
+        };
+        card.due = today + card.interval;
+    }
+
+    fn retrievability(&self, card: &CardSchedule, today: u64) -> f64 {
+        recall_probability(card.elapsed_days(today) as f64, card.stability.max(0.1))
+    }
+
+    /// Coordinate descent over the weights, minimizing the log loss of the recorded outcomes.
+    fn fit(&mut self, reviews: &[Review]) {
+        let histories = review_histories(reviews);
+        if histories.is_empty() {
+            return;
+        }
+
+        let mut best_loss = self.loss(&histories);
+        let mut step = 0.1;
+        for _ in 0..FIT_ROUNDS {
+            for (index, (low, high)) in WEIGHT_BOUNDS.iter().enumerate() {
+                let delta = step * (high - low);
+                for direction in [1.0, -1.0] {
+                    let mut candidate = self.clone();
+                    candidate.weights[index] =
+                        (self.weights[index] + direction * delta).clamp(*low, *high);
+
+                    let loss = candidate.loss(&histories);
+                    if loss < best_loss {
+                        best_loss = loss;
+                        *self = candidate;
+                    }
+                }
+            }
+            step /= 2.0;
+        }
+    }
+
+    fn header(&self) -> String {
+        let weights: Vec<String> = self.weights.iter().map(|w| format!("{w:.4}")).collect();
+        format!(
+            "fsrs retention={:.2} weights={}",
+            self.desired_retention,
+            weights.join(",")
+        )
+    }
+}
+
+impl FromStr for Fsrs {
+    type Err = ScheduleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ScheduleParseError::new(s);
+
+        let mut fsrs = Fsrs::default();
+        for field in s.split_whitespace() {
+            let (key, value) = field.split_once('=').ok_or_else(error)?;
+            match key {
+                "retention" => fsrs.desired_retention = value.parse().map_err(|_| error())?,
+                "weights" => {
+                    let weights = value
+                        .split(',')
+                        .map(|w| w.parse::<f64>())
+                        .collect::<Result<Vec<f64>, _>>()
+                        .map_err(|_| error())?;
+                    fsrs.weights = weights.try_into().map_err(|_| error())?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(fsrs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_review(fsrs: &Fsrs, grade: Grade) -> CardSchedule {
+        let mut card = CardSchedule::default();
+        fsrs.review(&mut card, grade, 0);
+        card
+    }
+
+    #[test]
+    fn first_intervals_follow_the_initial_stabilities() {
+        let fsrs = Fsrs::default();
+        let intervals: Vec<u64> = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy]
+            .into_iter()
+            .map(|grade| first_review(&fsrs, grade).interval)
+            .collect();
+
+        // At 90% retention a card is due after its stability in days.
+        assert_eq!(intervals, vec![1, 1, 4, 14]);
+    }
+
+    #[test]
+    fn recalling_on_the_due_day_lengthens_the_interval() {
+        let fsrs = Fsrs::default();
+        let mut card = first_review(&fsrs, Grade::Good);
+        let first = card.interval;
+        let due = card.due;
+
+        fsrs.review(&mut card, Grade::Good, due);
+
+        assert!(card.interval > first, "{} <= {first}", card.interval);
+        assert_eq!(card.repetitions, 2);
+    }
+
+    #[test]
+    fn forgetting_lowers_stability_and_counts_a_lapse() {
+        let fsrs = Fsrs::default();
+        let mut card = first_review(&fsrs, Grade::Good);
+        let stability = card.stability;
+        let due = card.due;
+
+        fsrs.review(&mut card, Grade::Again, due);
+
+        assert!(card.stability < stability);
+        assert_eq!(card.interval, 1);
+        assert_eq!(card.lapses, 1);
+        assert_eq!(card.repetitions, 0);
+    }
+
+    #[test]
+    fn higher_retention_gives_shorter_intervals() {
+        let stability = 20.0;
+
+        assert_eq!(Fsrs::new(0.9).next_interval(stability), 20);
+        assert!(Fsrs::new(0.95).next_interval(stability) < 20);
+        assert!(Fsrs::new(0.8).next_interval(stability) > 20);
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let fsrs = Fsrs::new(0.85);
+        let header = fsrs.header();
+        let parameters = header.strip_prefix("fsrs ").unwrap();
+
+        assert_eq!(parameters.parse::<Fsrs>().unwrap(), fsrs);
+    }
+}
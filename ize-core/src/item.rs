@@ -0,0 +1,330 @@
+//! The items reviewed in a run. A plain card gives one item per direction it is studied in and a
+//! cloze card one item per cloze number.
+//!
+//! Runs keep item ids as numbers: the card id in the low bits, then the cloze number, then the
+//! deck's number in a run over several decks, then a flag for the reverse direction. Run files
+//! write them as `12`, `12:reverse` or `12:c2`, prefixed with `2/` for cards of the deck numbered
+//! 2. The first deck is numbered 0 and has no prefix, so the ids of single deck runs are plain card
+//! ids.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    cloze::{self, Span},
+    Card, Deck,
+};
+
+/// Anki note ids, which are millisecond timestamps, fit in the card id bits for millennia. Targets
+/// with 32-bit ids have room for about a million cards per deck.
+#[cfg(target_pointer_width = "64")]
+const CLOZE_SHIFT: u32 = 48;
+#[cfg(not(target_pointer_width = "64"))]
+const CLOZE_SHIFT: u32 = 20;
+const DECK_SHIFT: u32 = CLOZE_SHIFT + 8;
+const REVERSE_FLAG: usize = 1 << (usize::BITS - 1);
+const CARD_ID_MASK: usize = (1 << CLOZE_SHIFT) - 1;
+/// Room for cloze numbers up to 255.
+const CLOZE_MASK: usize = (1 << DECK_SHIFT) - 1 - CARD_ID_MASK;
+const DECK_MASK: usize = !(CARD_ID_MASK | CLOZE_MASK | REVERSE_FLAG);
+
+/// The highest id a card can have. Higher bits of item ids hold the cloze and deck numbers.
+pub const MAX_CARD_ID: usize = CARD_ID_MASK;
+
+/// The most decks a run can study together.
+pub const MAX_DECKS: usize = (DECK_MASK >> DECK_SHIFT) + 1;
+
+const REVERSE_SUFFIX: &str = "reverse";
+const CLOZE_SUFFIX: &str = "c";
+const DECK_SEPARATOR: char = '/';
+
+/// Which way round a plain card is studied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Direction {
+    /// Shown the front, recall the back.
+    #[default]
+    Forward,
+    /// Shown the back, recall the front.
+    Reverse,
+    /// Both of the above, as separate items.
+    Both,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 3] = [Direction::Forward, Direction::Reverse, Direction::Both];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Forward => "forward",
+            Direction::Reverse => "reverse",
+            Direction::Both => "both",
+        }
+    }
+
+    fn kinds(self) -> &'static [ItemKind] {
+        match self {
+            Direction::Forward => &[ItemKind::Forward],
+            Direction::Reverse => &[ItemKind::Reverse],
+            Direction::Both => &[ItemKind::Forward, ItemKind::Reverse],
+        }
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Direction::ALL
+            .into_iter()
+            .find(|direction| direction.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown direction '{}'", s.trim()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Forward,
+    Reverse,
+    /// One cloze number of a cloze card.
+    Cloze(usize),
+}
+
+/// The item or card id as it is known in a run over several decks, where `deck` is the deck's
+/// position.
+pub fn deck_item_id(deck: usize, id: usize) -> usize {
+    local_item_id(id) | ((deck << DECK_SHIFT) & DECK_MASK)
+}
+
+/// The position of the deck an item or card id belongs to.
+pub fn deck_index(id: usize) -> usize {
+    (id & DECK_MASK) >> DECK_SHIFT
+}
+
+/// The id without its deck number, as the deck itself knows the item.
+pub fn local_item_id(id: usize) -> usize {
+    id & !DECK_MASK
+}
+
+/// `card_id` keeps any deck number given to it by [`deck_item_id`].
+pub fn item_id(card_id: usize, kind: ItemKind) -> usize {
+    let card_id = card_id & (CARD_ID_MASK | DECK_MASK);
+    match kind {
+        ItemKind::Forward => card_id,
+        ItemKind::Reverse => card_id | REVERSE_FLAG,
+        ItemKind::Cloze(number) => card_id | ((number << CLOZE_SHIFT) & CLOZE_MASK),
+    }
+}
+
+/// The card id, with any deck number, and which part of the card the item is.
+pub fn split_item_id(item_id: usize) -> (usize, ItemKind) {
+    let card_id = item_id & (CARD_ID_MASK | DECK_MASK);
+    let number = (item_id & CLOZE_MASK) >> CLOZE_SHIFT;
+
+    let kind = if number > 0 {
+        ItemKind::Cloze(number)
+    } else if item_id & REVERSE_FLAG != 0 {
+        ItemKind::Reverse
+    } else {
+        ItemKind::Forward
+    };
+
+    (card_id, kind)
+}
+
+/// The item id as written in run files.
+pub fn format_item_id(item_id: usize) -> String {
+    let deck = match deck_index(item_id) {
+        0 => String::new(),
+        deck => format!("{deck}{DECK_SEPARATOR}"),
+    };
+
+    match split_item_id(local_item_id(item_id)) {
+        (card_id, ItemKind::Forward) => format!("{deck}{card_id}"),
+        (card_id, ItemKind::Reverse) => format!("{deck}{card_id}:{REVERSE_SUFFIX}"),
+        (card_id, ItemKind::Cloze(number)) => format!("{deck}{card_id}:{CLOZE_SUFFIX}{number}"),
+    }
+}
+
+/// Read an item id written by [`format_item_id`]. Plain numbers are taken as they are.
+pub fn parse_item_id(text: &str) -> Option<usize> {
+    let text = text.trim();
+    let (deck, text) = match text.split_once(DECK_SEPARATOR) {
+        Some((deck, rest)) => (deck.parse().ok().filter(|deck| *deck < MAX_DECKS)?, rest),
+        None => (0, text),
+    };
+
+    let Some((card_id, suffix)) = text.split_once(':') else {
+        return text.parse().ok().map(|id| deck_item_id(deck, id));
+    };
+
+    let card_id = deck_item_id(deck, card_id.parse().ok()?);
+    let kind = if suffix == REVERSE_SUFFIX {
+        ItemKind::Reverse
+    } else {
+        let number = suffix.strip_prefix(CLOZE_SUFFIX)?.parse().ok()?;
+        ItemKind::Cloze(number)
+    };
+
+    Some(item_id(card_id, kind))
+}
+
+/// A single reviewable part of a card.
+pub struct Item<'a> {
+    pub card: &'a Card,
+    pub kind: ItemKind,
+}
+
+impl Item<'_> {
+    pub fn front(&self) -> Vec<Span> {
+        match self.kind {
+            ItemKind::Forward => vec![Span::Text(self.card.front.clone())],
+            ItemKind::Reverse => vec![Span::Text(self.card.back.clone())],
+            ItemKind::Cloze(number) => cloze::front_spans(&self.card.front, number),
+        }
+    }
+
+    /// For cloze cards the revealed text, followed by the card's back if it has one.
+    pub fn back(&self) -> Vec<Span> {
+        match self.kind {
+            ItemKind::Forward => vec![Span::Text(self.card.back.clone())],
+            ItemKind::Reverse => vec![Span::Text(self.card.front.clone())],
+            ItemKind::Cloze(number) => {
+                let mut spans = cloze::back_spans(&self.card.front, number);
+                if !self.card.back.trim().is_empty() {
+                    spans.push(Span::Text(format!("\n\n{}", self.card.back)));
+                }
+                spans
+            }
+        }
+    }
+
+    /// What has to be recalled: the back, the front when reversed, or the hidden terms of a cloze.
+    pub fn answer(&self) -> String {
+        match self.kind {
+            ItemKind::Forward => self.card.back.clone(),
+            ItemKind::Reverse => self.card.front.clone(),
+            ItemKind::Cloze(number) => {
+                let answers: Vec<String> = cloze::back_spans(&self.card.front, number)
+                    .into_iter()
+                    .filter_map(|span| match span {
+                        Span::Answer(answer) => Some(answer),
+                        _ => None,
+                    })
+                    .collect();
+                answers.join(" ")
+            }
+        }
+    }
+}
+
+impl Deck {
+    /// The kinds of item the card is studied as.
+    fn item_kinds(&self, card: &Card) -> Vec<ItemKind> {
+        let numbers = cloze::cloze_numbers(&card.front);
+        if !numbers.is_empty() {
+            return numbers.into_iter().map(ItemKind::Cloze).collect();
+        }
+
+        card.direction.unwrap_or(self.direction).kinds().to_vec()
+    }
+
+    /// Ids of everything reviewed in a run of the deck.
+    pub fn item_ids(&self) -> Vec<usize> {
+        self.cards
+            .values()
+            .flat_map(|card| {
+                self.item_kinds(card)
+                    .into_iter()
+                    .map(|kind| item_id(card.card_id, kind))
+            })
+            .collect()
+    }
+
+    pub fn item(&self, item_id: usize) -> Option<Item<'_>> {
+        let (card_id, kind) = split_item_id(item_id);
+        let card = self.cards.get(&card_id)?;
+
+        self.item_kinds(card)
+            .contains(&kind)
+            .then_some(Item { card, kind })
+    }
+
+    pub fn has_item(&self, item_id: usize) -> bool {
+        self.item(item_id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_are_packed_next_to_the_card_id() {
+        for kind in [ItemKind::Forward, ItemKind::Reverse, ItemKind::Cloze(255)] {
+            assert_eq!(
+                split_item_id(item_id(MAX_CARD_ID, kind)),
+                (MAX_CARD_ID, kind)
+            );
+        }
+        assert_eq!(item_id(12, ItemKind::Forward), 12);
+        assert_ne!(
+            item_id(12, ItemKind::Reverse),
+            item_id(12, ItemKind::Cloze(1))
+        );
+    }
+
+    #[test]
+    fn item_ids_are_written_with_their_kind() {
+        for (kind, text) in [
+            (ItemKind::Forward, "12"),
+            (ItemKind::Reverse, "12:reverse"),
+            (ItemKind::Cloze(2), "12:c2"),
+        ] {
+            let id = item_id(12, kind);
+
+            assert_eq!(format_item_id(id), text);
+            assert_eq!(parse_item_id(text), Some(id));
+        }
+    }
+
+    #[test]
+    fn unknown_suffixes_are_not_item_ids() {
+        for text in ["12:forward", "12:c", "12:cx", "x:reverse", ""] {
+            assert_eq!(parse_item_id(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn deck_numbers_are_written_before_the_card_id() {
+        let id = item_id(deck_item_id(2, 12), ItemKind::Reverse);
+
+        assert_eq!(deck_index(id), 2);
+        assert_eq!(local_item_id(id), item_id(12, ItemKind::Reverse));
+        assert_eq!(format_item_id(id), "2/12:reverse");
+        assert_eq!(parse_item_id("2/12:reverse"), Some(id));
+        assert_eq!(parse_item_id("2/12"), Some(deck_item_id(2, 12)));
+        // The first deck has no prefix, so single deck runs keep plain card ids.
+        assert_eq!(format_item_id(deck_item_id(0, 12)), "12");
+    }
+
+    #[test]
+    fn deck_numbers_past_the_limit_are_not_item_ids() {
+        let last = MAX_DECKS - 1;
+
+        assert_eq!(
+            parse_item_id(&format!("{last}/12")),
+            Some(deck_item_id(last, 12))
+        );
+        assert_eq!(parse_item_id(&format!("{MAX_DECKS}/12")), None);
+    }
+}
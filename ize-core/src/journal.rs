@@ -0,0 +1,290 @@
+//! Undo and redo of the actions taken on a run.
+//!
+//! Every action which moves or reorders cards keeps a copy of the piles as they were before it,
+//! along with the schedule of any card it graded. Actions made up of others, like starting a
+//! session, are undone in one step. The journal lives only as long as the run is open and isn't
+//! saved with it. Review logs are only ever appended to, so undoing a grading logs its record
+//! again marked as undone, and redoing it logs the record once more.
+
+use std::collections::HashMap;
+
+use crate::{review_log::ReviewRecord, scheduler::CardSchedule, PracticeRun};
+
+/// The most actions which can be undone.
+const JOURNAL_LIMIT: usize = 100;
+
+/// The state of a run before or after an action.
+#[derive(Debug)]
+struct Snapshot {
+    remaining: Vec<usize>,
+    boxes: Vec<Vec<usize>>,
+    origins: HashMap<usize, usize>,
+    relearning: HashMap<usize, usize>,
+    session: usize,
+    /// Restored so that shuffles after an undo draw as they did the first time.
+    shuffles: u64,
+    last_graded: Option<usize>,
+    /// Schedules of the cards graded by the action, `None` for cards without one.
+    schedules: Vec<(usize, Option<CardSchedule>)>,
+    /// Records logged for the gradings of the action.
+    records: Vec<ReviewRecord>,
+}
+
+impl Snapshot {
+    fn same_piles(&self, other: &Snapshot) -> bool {
+        self.remaining == other.remaining
+            && self.boxes == other.boxes
+            && self.origins == other.origins
+            && self.relearning == other.relearning
+            && self.session == other.session
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RunJournal {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    /// The state before the action being taken, while one is.
+    pending: Option<Snapshot>,
+}
+
+impl PracticeRun {
+    fn snapshot(&self, schedule_ids: impl Iterator<Item = usize>) -> Snapshot {
+        Snapshot {
+            remaining: self.remaining.clone(),
+            boxes: self
+                .boxes
+                .iter()
+                .map(|card_box| card_box.ids.clone())
+                .collect(),
+            origins: self.origins.clone(),
+            relearning: self.relearning.clone(),
+            session: self.session,
+            shuffles: self.shuffles,
+            last_graded: self.last_graded,
+            schedules: schedule_ids
+                .map(|id| (id, self.schedule.cards.get(&id).copied()))
+                .collect(),
+            records: vec![],
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.remaining = snapshot.remaining;
+        for (card_box, ids) in self.boxes.iter_mut().zip(snapshot.boxes) {
+            card_box.ids = ids;
+        }
+        self.origins = snapshot.origins;
+        self.relearning = snapshot.relearning;
+        self.session = snapshot.session;
+        self.shuffles = snapshot.shuffles;
+        self.last_graded = snapshot.last_graded;
+
+        for (id, schedule) in snapshot.schedules {
+            match schedule {
+                Some(schedule) => {
+                    self.schedule.cards.insert(id, schedule);
+                }
+                None => self.schedule.remove(id),
+            }
+        }
+    }
+
+    /// Take `action` as one step of the journal. Actions taken within it are part of the same
+    /// step, and actions which change nothing aren't recorded.
+    pub(crate) fn journaled<T>(&mut self, action: impl FnOnce(&mut Self) -> T) -> T {
+        if self.journal.pending.is_some() {
+            return action(self);
+        }
+
+        self.journal.pending = Some(self.snapshot(std::iter::empty()));
+        let result = action(self);
+        let Some(before) = self.journal.pending.take() else {
+            return result;
+        };
+
+        if !before.same_piles(&self.snapshot(std::iter::empty())) || !before.schedules.is_empty() {
+            self.journal.undo.push(before);
+            if self.journal.undo.len() > JOURNAL_LIMIT {
+                self.journal.undo.remove(0);
+            }
+            self.journal.redo.clear();
+        }
+
+        result
+    }
+
+    /// Keep the schedule of a card about to be graded so undoing the action restores it.
+    pub(crate) fn journal_schedule(&mut self, id: usize) {
+        let schedule = self.schedule.cards.get(&id).copied();
+        if let Some(pending) = &mut self.journal.pending {
+            if !pending.schedules.iter().any(|(noted, _)| *noted == id) {
+                pending.schedules.push((id, schedule));
+            }
+        }
+    }
+
+    /// Keep `record` with the action taken last, so it is marked as undone along with it.
+    pub(crate) fn journal_record(&mut self, record: &ReviewRecord) {
+        if let Some(last) = self.journal.undo.last_mut() {
+            last.records.push(record.clone());
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.journal.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.journal.redo.is_empty()
+    }
+
+    /// Put the run back as it was before the last action. Returns false if there is nothing to
+    /// undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(mut before) = self.journal.undo.pop() else {
+            return false;
+        };
+
+        let mut after = self.snapshot(before.schedules.iter().map(|(id, _)| *id));
+        after.records = std::mem::take(&mut before.records);
+        self.log_undo(&after.records, true);
+        self.restore(before);
+        self.journal.redo.push(after);
+
+        true
+    }
+
+    /// Take the last undone action again. Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(mut after) = self.journal.redo.pop() else {
+            return false;
+        };
+
+        let mut before = self.snapshot(after.schedules.iter().map(|(id, _)| *id));
+        before.records = std::mem::take(&mut after.records);
+        self.log_undo(&before.records, false);
+        self.restore(after);
+        self.journal.undo.push(before);
+
+        true
+    }
+
+    /// Forget every action taken so far, so they can no longer be undone.
+    pub fn clear_journal(&mut self) {
+        self.journal = RunJournal::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        leitner::BoxLayout,
+        review_log::{without_undone, ReviewTimer},
+        run_actions::RunCategory,
+        sm2::Sm2,
+        Card, Deck, PracticeRun,
+    };
+
+    fn run() -> PracticeRun {
+        let mut deck = Deck::new();
+        for card_id in 1..=8 {
+            deck.cards.insert(
+                card_id,
+                Card {
+                    card_id,
+                    front: format!("front {card_id}"),
+                    back: format!("back {card_id}"),
+                    direction: None,
+                    tags: vec![],
+                },
+            );
+        }
+
+        PracticeRun::new_from_deck(&deck, Box::new(Sm2), BoxLayout::Leitner(3).boxes()).with_seed(3)
+    }
+
+    #[test]
+    fn undo_and_redo_move_the_card_and_its_schedule() {
+        let mut run = run();
+        let remaining = run.remaining.clone();
+        let id = *remaining.last().unwrap();
+
+        run.move_last(RunCategory::Remaining, RunCategory::Box(2))
+            .unwrap();
+        let graded = run.schedule.cards[&id];
+
+        assert!(run.undo());
+        assert_eq!(run.remaining, remaining);
+        assert!(run.boxes[2].ids.is_empty());
+        assert!(!run.schedule.cards.contains_key(&id));
+        assert_eq!(run.last_graded, None);
+
+        assert!(run.redo());
+        assert_eq!(run.boxes[2].ids, vec![id]);
+        assert_eq!(run.schedule.cards[&id], graded);
+        assert_eq!(run.last_graded, Some(id));
+        assert!(!run.can_redo());
+    }
+
+    #[test]
+    fn shuffles_after_an_undo_draw_as_they_did_the_first_time() {
+        let mut run = run();
+        run.shuffle(RunCategory::Remaining);
+        let shuffled = run.remaining.clone();
+        let shuffles = run.shuffles;
+
+        assert!(run.undo());
+        assert_eq!(run.shuffles, shuffles - 1);
+        run.shuffle(RunCategory::Remaining);
+
+        assert_eq!(run.remaining, shuffled);
+    }
+
+    #[test]
+    fn undo_gives_back_the_card_graded_before() {
+        let mut run = run();
+        run.move_last(RunCategory::Remaining, RunCategory::Box(1))
+            .unwrap();
+        let first = run.last_graded;
+        run.move_last(RunCategory::Remaining, RunCategory::Box(1))
+            .unwrap();
+
+        assert_ne!(run.last_graded, first);
+        assert!(run.undo());
+        assert_eq!(run.last_graded, first);
+    }
+
+    #[test]
+    fn a_new_action_drops_what_could_be_redone() {
+        let mut run = run();
+        run.move_last(RunCategory::Remaining, RunCategory::Box(0))
+            .unwrap();
+        assert!(run.undo());
+        assert!(run.can_redo());
+
+        run.move_last(RunCategory::Remaining, RunCategory::Box(1))
+            .unwrap();
+
+        assert!(!run.can_redo());
+        assert!(run.undo());
+        assert!(!run.can_undo());
+    }
+
+    #[test]
+    fn undone_gradings_are_marked_in_the_review_log() {
+        let mut run = run();
+        let id = *run.remaining.last().unwrap();
+        let record = run
+            .review_record(id, RunCategory::Box(1), &ReviewTimer::start())
+            .unwrap();
+        run.move_last(RunCategory::Remaining, RunCategory::Box(1))
+            .unwrap();
+        run.log_review(record.clone()).unwrap();
+
+        assert!(run.undo());
+        assert!(without_undone(&run.review_history()).is_empty());
+        assert!(run.redo());
+        assert_eq!(without_undone(&run.review_history()), vec![record]);
+    }
+}
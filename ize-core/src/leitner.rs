@@ -0,0 +1,77 @@
+/// A pile of cards in a practice run.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CardBox {
+    pub name: String,
+    /// The box is due every `review_frequency` sessions.
+    pub review_frequency: usize,
+    /// Index of the box a card from this box moves to when answered correctly.
+    pub promote_to: usize,
+    /// Index of the box a card from this box moves to when answered incorrectly.
+    pub demote_to: usize,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_io::item_ids"))]
+    pub ids: Vec<usize>,
+}
+
+impl CardBox {
+    pub fn new(name: &str, review_frequency: usize, promote_to: usize, demote_to: usize) -> Self {
+        CardBox {
+            name: name.to_string(),
+            review_frequency: review_frequency.max(1),
+            promote_to,
+            demote_to,
+            ids: vec![],
+        }
+    }
+
+    pub fn is_due(&self, session: usize) -> bool {
+        session.is_multiple_of(self.review_frequency)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoxLayout {
+    /// Incorrect, Working and Memorized piles which are all reviewed every session.
+    Piles,
+    /// Boxes reviewed half as often as the one before, where correct answers move a card up a box
+    /// and incorrect ones move it back to the first.
+    Leitner(usize),
+}
+
+impl BoxLayout {
+    pub const ALL: [BoxLayout; 3] = [
+        BoxLayout::Piles,
+        BoxLayout::Leitner(5),
+        BoxLayout::Leitner(7),
+    ];
+
+    pub fn name(self) -> String {
+        match self {
+            BoxLayout::Piles => "Incorrect, Working, Memorized".to_string(),
+            BoxLayout::Leitner(count) => format!("Leitner, {} boxes", count),
+        }
+    }
+
+    pub fn boxes(self) -> Vec<CardBox> {
+        match self {
+            BoxLayout::Piles => vec![
+                CardBox::new("Incorrect", 1, 2, 0),
+                CardBox::new("Working", 1, 2, 0),
+                CardBox::new("Memorized", 1, 2, 0),
+            ],
+            BoxLayout::Leitner(count) => {
+                let count = count.max(1);
+                (0..count)
+                    .map(|i| {
+                        CardBox::new(
+                            &format!("Box {}", i + 1),
+                            1 << i.min(30),
+                            (i + 1).min(count - 1),
+                            0,
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+}
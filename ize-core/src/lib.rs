@@ -1,56 +1,283 @@
 use std::collections::HashMap;
 
+#[cfg(feature = "anki")]
+pub mod anki;
+pub mod answer;
+pub mod atomic_write;
+pub mod card_ids;
+pub mod cli;
+pub mod cloze;
+pub mod csv_deck;
 mod deck_io;
+pub mod error;
+pub mod fsrs;
+pub mod item;
+mod journal;
+pub mod leitner;
+pub mod multiple_choice;
+pub mod ordering;
+pub mod review_log;
 mod run_actions;
+pub mod scheduler;
+#[cfg(feature = "serde")]
+mod serde_io;
+pub mod session;
+pub mod sm2;
+pub mod tags;
+
+use card_ids::IdScheme;
+use item::Direction;
+use leitner::{BoxLayout, CardBox};
+use ordering::CardOrder;
+use rand::RngCore;
+use review_log::ReviewRecord;
+use scheduler::{Schedule, Scheduler};
+use tags::TagFilter;
 
 pub mod prelude {
+    pub use super::answer::{check_answer, AnswerCheck, AnswerOptions, Diff};
+    pub use super::atomic_write::{SaveError, SaveOptions};
+    pub use super::card_ids::IdScheme;
+    pub use super::cli::take_seed_arg;
+    pub use super::cloze::Span;
+    pub use super::csv_deck::{load_csv_deck, save_csv_deck, Column, CsvOptions};
     pub use super::deck_io::load_deck;
+    pub use super::deck_io::load_decks;
     pub use super::deck_io::load_practice_run;
+    pub use super::deck_io::load_practice_run_with_deck;
     pub use super::deck_io::save_deck;
+    pub use super::deck_io::save_deck_with_options;
     pub use super::deck_io::save_practice_run;
+    pub use super::deck_io::save_practice_run_with_options;
+    pub use super::deck_io::stamp_ids;
+    pub use super::error::IzeError;
+    pub use super::item::{Direction, Item, ItemKind};
+    pub use super::leitner::{BoxLayout, CardBox};
+    pub use super::multiple_choice::{MultipleChoice, DISTRACTOR_COUNT};
+    pub use super::ordering::CardOrder;
+    pub use super::review_log::{
+        append_review, load_review_log, review_log_path, without_undone, ReviewRecord, ReviewTimer,
+    };
     pub use super::run_actions::*;
+    pub use super::scheduler::{today, Algorithm, Grade, Scheduler};
+    pub use super::session::{format_duration, PracticeSession, SessionLimits, SessionSummary};
+    pub use super::tags::{parse_tags, TagFilter};
     pub use super::Card;
     pub use super::PracticeRun;
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct PracticeRun {
     pub deck_path: String,
+    /// Decks studied along with the one at `deck_path`, numbered from 1 in item ids.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub other_deck_paths: Vec<String>,
+    /// Where the run was loaded from or last saved to.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub last_save: String,
 
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_io::item_ids"))]
     pub remaining: Vec<usize>,
-    pub memorized: Vec<usize>,
-    pub working: Vec<usize>,
-    pub incorrect: Vec<usize>,
+    /// Ordered from the box for the least known cards to the box for the best known.
+    pub boxes: Vec<CardBox>,
+    /// The box each card in `remaining` was taken from.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_io::id_map"))]
+    pub origins: HashMap<usize, usize>,
+    /// Number of sessions started, used to decide which boxes are due.
+    pub session: usize,
+    /// Picks the cards of the deck which take part in the run.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "TagFilter::is_empty"))]
+    pub filter: TagFilter,
+    /// After how many cards a card answered wrongly comes back, see
+    /// [`run_actions::DEFAULT_LEARNING_STEPS`].
+    pub learning_steps: Vec<usize>,
+    /// How piles are put in order when they are shuffled.
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "CardOrder::is_uniform")
+    )]
+    pub order: CardOrder,
+    /// Seeds the shuffles of the run, so that the same seed puts the same cards in the same order.
+    seed: u64,
+    /// Shuffles and other draws made so far, each from its own generator seeded from `seed`.
+    shuffles: u64,
+
+    pub schedule: Schedule,
+
+    /// Cards in the remaining pile which were answered wrongly, with the number of times they have
+    /// come back.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            with = "crate::serde_io::id_map",
+            skip_serializing_if = "HashMap::is_empty"
+        )
+    )]
+    relearning: HashMap<usize, usize>,
+    /// The card graded last, kept from being the first of the next pass.
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            with = "crate::serde_io::optional_item_id",
+            skip_serializing_if = "Option::is_none"
+        )
+    )]
+    last_graded: Option<usize>,
+    /// Used for shuffling instead of `seed` if the caller provided one.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    rng: Option<Box<dyn RngCore>>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    journal: journal::RunJournal,
+    /// Reviews made before the run was first saved, written to its review log once it is.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    unlogged: Vec<ReviewRecord>,
+}
+
+impl Default for PracticeRun {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PracticeRun {
     pub fn new() -> Self {
         PracticeRun {
             deck_path: "".to_string(),
+            other_deck_paths: vec![],
             last_save: "".to_string(),
             remaining: vec![],
-            memorized: vec![],
-            working: vec![],
-            incorrect: vec![],
+            boxes: BoxLayout::Piles.boxes(),
+            origins: HashMap::new(),
+            session: 0,
+            filter: TagFilter::default(),
+            learning_steps: run_actions::DEFAULT_LEARNING_STEPS.to_vec(),
+            order: CardOrder::default(),
+            seed: rand::random(),
+            shuffles: 0,
+            schedule: Schedule::default(),
+            relearning: HashMap::new(),
+            last_graded: None,
+            rng: None,
+            journal: journal::RunJournal::default(),
+            unlogged: vec![],
         }
     }
 
-    pub fn new_from_deck(deck: &Deck) -> Self {
+    pub fn new_from_deck(deck: &Deck, scheduler: Box<dyn Scheduler>, boxes: Vec<CardBox>) -> Self {
+        Self::new_from_deck_with_filter(deck, TagFilter::default(), scheduler, boxes)
+    }
+
+    /// A run over the cards of the deck which pass `filter`.
+    pub fn new_from_deck_with_filter(
+        deck: &Deck,
+        filter: TagFilter,
+        scheduler: Box<dyn Scheduler>,
+        boxes: Vec<CardBox>,
+    ) -> Self {
         let mut run = Self::new();
+        run.schedule = Schedule::new(scheduler);
+        run.boxes = boxes;
 
-        run.remaining = deck.cards.iter().map(|(id, _)| *id).collect();
+        run.remaining = deck.filtered_item_ids(&filter);
+        run.filter = filter;
         run.shuffle(run_actions::RunCategory::Remaining);
+        run.clear_journal();
 
         run
     }
+
+    /// Every deck of the run, in the order they are numbered in item ids.
+    pub fn deck_paths(&self) -> Vec<&str> {
+        std::iter::once(self.deck_path.as_str())
+            .chain(self.other_deck_paths.iter().map(|path| path.as_str()))
+            .collect()
+    }
+
+    pub fn set_deck_paths(&mut self, paths: &[String]) {
+        self.deck_path = paths.first().cloned().unwrap_or_default();
+        self.other_deck_paths = paths.iter().skip(1).cloned().collect();
+    }
 }
 
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "crate::serde_io::DeckFile"))]
 pub struct Deck {
+    pub title: Option<String>,
+    /// How cards without a direction of their own are studied.
+    pub direction: Direction,
+    /// How cards written without an id are given one.
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "IdScheme::is_sequential")
+    )]
+    pub id_scheme: IdScheme,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serde_io::card_list")
+    )]
     pub cards: HashMap<usize, Card>,
+    /// Cards which were given an id when the deck was loaded, as the file has none for them.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    generated_ids: usize,
+}
+
+impl Deck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An id not used by any card in the deck.
+    pub fn next_card_id(&self) -> usize {
+        self.cards.keys().max().map_or(1, |id| id + 1)
+    }
+
+    /// One deck holding the cards of all of `decks`, with card ids numbered by deck as in a run
+    /// over several decks. The directions of the decks are kept on their cards.
+    pub fn combine(decks: Vec<Deck>) -> Deck {
+        if decks.len() == 1 {
+            return decks.into_iter().next().unwrap_or_default();
+        }
+
+        let titles: Vec<String> = decks.iter().filter_map(|deck| deck.title.clone()).collect();
+        let mut combined = Deck::new();
+        combined.title = (!titles.is_empty()).then(|| titles.join(" + "));
+
+        for (index, deck) in decks.into_iter().enumerate() {
+            let deck_direction = (deck.direction != Direction::Forward).then_some(deck.direction);
+            for card in deck.cards.into_values() {
+                let card_id = item::deck_item_id(index, card.card_id);
+                combined.cards.insert(
+                    card_id,
+                    Card {
+                        card_id,
+                        direction: card.direction.or(deck_direction),
+                        ..card
+                    },
+                );
+            }
+        }
+
+        combined
+    }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Card {
     pub card_id: usize,
     pub front: String,
     pub back: String,
+    /// Overrides the deck's direction.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub direction: Option<Direction>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub tags: Vec<String>,
 }
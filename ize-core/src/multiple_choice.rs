@@ -0,0 +1,84 @@
+//! Multiple choice questions, where the answer of an item is offered among the answers of other
+//! items in the deck.
+
+use std::mem::discriminant;
+
+use rand::{seq::SliceRandom, RngCore};
+
+use crate::Deck;
+
+/// Number of wrong answers offered next to the right one.
+pub const DISTRACTOR_COUNT: usize = 3;
+
+pub struct MultipleChoice {
+    pub choices: Vec<String>,
+    /// Index of the right answer in `choices`.
+    pub correct: usize,
+}
+
+impl MultipleChoice {
+    pub fn is_correct(&self, choice: usize) -> bool {
+        choice == self.correct
+    }
+}
+
+fn comparable(answer: &str) -> String {
+    answer.trim().to_lowercase()
+}
+
+impl Deck {
+    /// The answer of the item shuffled in with up to `distractors` answers of other items of the
+    /// same kind, drawn by `rng` from those sharing a tag with it and closest to it in length.
+    pub fn multiple_choice(
+        &self,
+        item_id: usize,
+        distractors: usize,
+        rng: &mut dyn RngCore,
+    ) -> Option<MultipleChoice> {
+        let item = self.item(item_id)?;
+        let answer = item.answer();
+        let length = answer.chars().count();
+
+        let mut seen = vec![comparable(&answer)];
+        let mut candidates: Vec<(bool, String)> = vec![];
+        for id in self.item_ids() {
+            let Some(other) = self.item(id) else {
+                continue;
+            };
+            if discriminant(&other.kind) != discriminant(&item.kind) {
+                continue;
+            }
+
+            let other_answer = other.answer();
+            let key = comparable(&other_answer);
+            if key.is_empty() || seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+
+            let shares_tag = other.card.tags.iter().any(|tag| item.card.has_tag(tag));
+            candidates.push((shares_tag, other_answer));
+        }
+
+        // Drawing from twice as many as needed keeps the same distractors from always appearing.
+        // The answer breaks ties, so the order doesn't depend on the order the items come in.
+        candidates.sort_by_key(|(shares_tag, candidate)| {
+            (
+                !shares_tag,
+                candidate.chars().count().abs_diff(length),
+                candidate.clone(),
+            )
+        });
+        candidates.truncate(distractors * 2);
+
+        let mut choices: Vec<String> = candidates
+            .choose_multiple(rng, distractors)
+            .map(|(_, candidate)| candidate.clone())
+            .collect();
+        choices.push(answer.clone());
+        choices.shuffle(rng);
+
+        let correct = choices.iter().position(|choice| *choice == answer)?;
+        Some(MultipleChoice { choices, correct })
+    }
+}
@@ -0,0 +1,222 @@
+//! The order in which the cards of a pile are shown.
+//!
+//! Shuffles, and the options of multiple choice questions, draw from a ChaCha8 generator seeded
+//! from the run's seed and the number of draws before them. Both are saved with the run, along
+//! with the order of the remaining pile and the card graded last, so a run started with a given
+//! seed shuffles the same way every time, saved and resumed or not.
+
+use std::{cmp::Reverse, fmt::Display, str::FromStr};
+
+use rand::{seq::SliceRandom, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    multiple_choice::MultipleChoice, run_actions::RunCategory, scheduler::today, Deck, PracticeRun,
+};
+
+/// How a pile is put in order when it is shuffled.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum CardOrder {
+    /// Every order is as likely.
+    #[default]
+    Uniform,
+    /// Random, but cards from the lower boxes and cards often forgotten tend to come first.
+    Weighted,
+    /// By id, lowest first.
+    Sequential,
+    /// Cards from the lowest box first, then those forgotten most often, then those least likely
+    /// to be recalled today.
+    HardestFirst,
+}
+
+impl CardOrder {
+    pub const ALL: [CardOrder; 4] = [
+        CardOrder::Uniform,
+        CardOrder::Weighted,
+        CardOrder::Sequential,
+        CardOrder::HardestFirst,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CardOrder::Uniform => "uniform",
+            CardOrder::Weighted => "weighted",
+            CardOrder::Sequential => "sequential",
+            CardOrder::HardestFirst => "hardest-first",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            CardOrder::Uniform => "Random",
+            CardOrder::Weighted => "Random, weak cards earlier",
+            CardOrder::Sequential => "By id",
+            CardOrder::HardestFirst => "Hardest first",
+        }
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        *self == CardOrder::Uniform
+    }
+}
+
+impl Display for CardOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for CardOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CardOrder::ALL
+            .into_iter()
+            .find(|order| order.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown card order '{}'", s.trim()))
+    }
+}
+
+impl PracticeRun {
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Shuffle from now on as a run started with `seed` would.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.shuffles = 0;
+        self.rng = None;
+    }
+
+    /// Shuffle with `rng` from now on instead of the run's seed, which then no longer says how the
+    /// run was shuffled.
+    pub fn set_rng(&mut self, rng: impl RngCore + 'static) {
+        self.rng = Some(Box::new(rng));
+    }
+
+    /// The run with its remaining pile shuffled again from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.set_seed(seed);
+        self.reorder_remaining();
+        self
+    }
+
+    /// The run with its remaining pile shuffled again by `rng`, which it goes on shuffling with.
+    pub fn with_rng(mut self, rng: impl RngCore + 'static) -> Self {
+        self.set_rng(rng);
+        self.reorder_remaining();
+        self
+    }
+
+    fn reorder_remaining(&mut self) {
+        self.shuffle(RunCategory::Remaining);
+        self.clear_journal();
+    }
+
+    /// The box a card in `category` counts as being in when ordering, lower for weaker cards.
+    fn order_box(&self, id: usize, category: RunCategory) -> usize {
+        match category {
+            RunCategory::Box(index) => index,
+            RunCategory::Remaining => self.origin(id),
+        }
+    }
+
+    fn lapses(&self, id: usize) -> u32 {
+        self.schedule.cards.get(&id).map_or(0, |card| card.lapses)
+    }
+
+    /// Call `draw` with the run's generator, which is the custom one if set and otherwise one
+    /// seeded from the seed and the number of draws so far. Unlike `StdRng`, ChaCha8 is promised to
+    /// give the same numbers in later versions of rand, so saved runs go on shuffling as before.
+    fn draw<T>(&mut self, draw: impl FnOnce(&Self, &mut dyn RngCore) -> T) -> T {
+        let mut custom = self.rng.take();
+        let mut seeded;
+        let rng: &mut dyn RngCore = match &mut custom {
+            Some(rng) => rng.as_mut(),
+            None => {
+                seeded = ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(self.shuffles));
+                &mut seeded
+            }
+        };
+        self.shuffles += 1;
+
+        let result = draw(self, rng);
+        self.rng = custom;
+        result
+    }
+
+    /// A multiple choice question for `item_id` of `deck`, with its options drawn from the run's
+    /// generator so the same seed offers them in the same order.
+    pub fn multiple_choice(
+        &mut self,
+        deck: &Deck,
+        item_id: usize,
+        distractors: usize,
+    ) -> Option<MultipleChoice> {
+        self.draw(|_, rng| deck.multiple_choice(item_id, distractors, rng))
+    }
+
+    /// Put `ids`, a pile of `category`, in the run's order. The last card is shown first.
+    pub(crate) fn order_ids(&mut self, ids: &mut [usize], category: RunCategory) {
+        self.draw(|run, rng| run.order_drawn(ids, category, rng));
+    }
+
+    fn order_drawn(&self, ids: &mut [usize], category: RunCategory, rng: &mut dyn RngCore) {
+        // Start from the same order whatever order the ids came in.
+        ids.sort_unstable();
+        ids.shuffle(rng);
+
+        match self.order {
+            CardOrder::Uniform => {}
+            CardOrder::Weighted => {
+                // Each card draws a key of u^(1/weight), and the highest keys are shown first, so
+                // heavier cards come up earlier. The weight counts down from the number of boxes
+                // for the first box to one for the last, plus one for every lapse.
+                let box_count = self.boxes.len();
+                let mut keyed: Vec<(f64, usize)> = ids
+                    .iter()
+                    .map(|id| {
+                        let weight = box_count
+                            .saturating_sub(self.order_box(*id, category))
+                            .max(1)
+                            + self.lapses(*id) as usize;
+                        (rng.gen::<f64>().powf(1.0 / weight as f64), *id)
+                    })
+                    .collect();
+                keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+                for (slot, (_, id)) in ids.iter_mut().zip(keyed) {
+                    *slot = id;
+                }
+            }
+            CardOrder::Sequential => ids.sort_by_key(|id| Reverse(*id)),
+            CardOrder::HardestFirst => {
+                let today = today();
+                // Sorting is stable, so cards alike stay shuffled.
+                ids.sort_by(|a, b| {
+                    let hardness = |id: usize| {
+                        (
+                            Reverse(self.order_box(id, category)),
+                            self.lapses(id),
+                            Reverse(self.schedule.retrievability(id, today)),
+                        )
+                    };
+                    hardness(*a)
+                        .partial_cmp(&hardness(*b))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+            }
+        }
+
+        // Don't start a pass with the card the last one ended on.
+        if self.order != CardOrder::Sequential
+            && ids.len() > 1
+            && ids.last() == self.last_graded.as_ref()
+        {
+            let len = ids.len();
+            ids.swap(len - 1, len - 2);
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::IzeError,
+    run_actions::RunCategory,
+    scheduler::{Grade, Review, SECONDS_PER_DAY},
+    PracticeRun,
+};
+
+/// One grading of a card during a practice run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewRecord {
+    pub card_id: usize,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// Name of the box the card was taken from, or "New" if it has never been sorted.
+    pub from: String,
+    pub to: String,
+    pub grade: Grade,
+    /// Time from the card being shown to it being graded.
+    pub time_to_answer: Duration,
+    pub flipped: bool,
+    /// Time from the card being shown to its back being shown, if it was. Older logs don't have
+    /// it.
+    pub time_to_flip: Option<Duration>,
+    /// Marks the copy of a record written when its grading was undone. Both are left out when
+    /// fitting, see [`without_undone`].
+    pub undone: bool,
+}
+
+impl ReviewRecord {
+    pub fn review(&self) -> Review {
+        Review {
+            card_id: self.card_id,
+            day: self.timestamp / SECONDS_PER_DAY,
+            grade: self.grade,
+        }
+    }
+}
+
+/// Measures how long the user takes to answer the card currently shown.
+#[derive(Debug, Clone, Copy)]
+pub struct ReviewTimer {
+    shown: Instant,
+    flipped: Option<Instant>,
+}
+
+impl Default for ReviewTimer {
+    fn default() -> Self {
+        Self::start()
+    }
+}
+
+impl ReviewTimer {
+    pub fn start() -> Self {
+        ReviewTimer {
+            shown: Instant::now(),
+            flipped: None,
+        }
+    }
+
+    /// Flipping again, e.g. back to the front and over, keeps the time of the first flip.
+    pub fn flip(&mut self) {
+        self.flipped.get_or_insert_with(Instant::now);
+    }
+
+    pub fn flipped(&self) -> bool {
+        self.flipped.is_some()
+    }
+
+    /// Time from the card being shown to it being flipped.
+    pub fn time_to_flip(&self) -> Option<Duration> {
+        self.flipped
+            .map(|flipped| flipped.duration_since(self.shown))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.shown.elapsed()
+    }
+}
+
+impl PracticeRun {
+    /// Describe grading the card `id` from the remaining pile into `destination_category`.
+    ///
+    /// Must be called before the card is moved. Skipping a card is not a grading, so returns `None`
+    /// if the destination is the remaining pile. The record is then passed to
+    /// [`PracticeRun::log_review`].
+    pub fn review_record(
+        &self,
+        id: usize,
+        destination_category: RunCategory,
+        timer: &ReviewTimer,
+    ) -> Option<ReviewRecord> {
+        let grade = self.grade(id, destination_category)?;
+        let from = match self.origins.get(&id) {
+            Some(index) => self.category_name(RunCategory::Box(*index)),
+            None => "New",
+        };
+
+        Some(ReviewRecord {
+            card_id: id,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            from: from.to_string(),
+            to: self.category_name(destination_category).to_string(),
+            grade,
+            time_to_answer: timer.elapsed(),
+            flipped: timer.flipped(),
+            time_to_flip: timer.time_to_flip(),
+            undone: false,
+        })
+    }
+
+    /// The review log kept next to the run file, or `None` until the run has been saved.
+    pub fn history_path(&self) -> Option<String> {
+        (!self.last_save.is_empty()).then(|| review_log_path(&self.last_save))
+    }
+
+    /// Append `record` to the run's review log. Records of a run which hasn't been saved yet are
+    /// held back and written when it is.
+    ///
+    /// The record belongs to the action taken last, so undoing that action marks it as undone.
+    pub fn log_review(&mut self, record: ReviewRecord) -> Result<(), IzeError> {
+        self.journal_record(&record);
+        match self.history_path() {
+            Some(path) => append_review(&path, &record),
+            None => {
+                self.unlogged.push(record);
+                Ok(())
+            }
+        }
+    }
+
+    /// Log copies of the records of an action being undone, marked as undone, or being redone.
+    /// Copies which can't be written are held back and tried again when the run is saved.
+    pub(crate) fn log_undo(&mut self, records: &[ReviewRecord], undone: bool) {
+        for record in records {
+            let record = ReviewRecord {
+                undone,
+                ..record.clone()
+            };
+            let written = self
+                .history_path()
+                .is_some_and(|path| append_review(&path, &record).is_ok());
+            if !written {
+                self.unlogged.push(record);
+            }
+        }
+    }
+
+    /// Note that the run was saved to `filepath`. A run saved somewhere new takes its review log
+    /// along, and the records held back so far are written.
+    pub(crate) fn saved_to(&mut self, filepath: &str) -> Result<(), IzeError> {
+        let previous = self.history_path();
+        self.last_save = fs::canonicalize(filepath).map_or(filepath.to_string(), |path| {
+            path.to_string_lossy().to_string()
+        });
+        let path = review_log_path(&self.last_save);
+
+        if let Some(previous) = previous.filter(|previous| *previous != path) {
+            if Path::new(&previous).exists() && !Path::new(&path).exists() {
+                fs::copy(&previous, &path).map_err(|e| IzeError::io(&path, e))?;
+            }
+        }
+
+        while let Some(record) = self.unlogged.first() {
+            append_review(&path, record)?;
+            self.unlogged.remove(0);
+        }
+
+        Ok(())
+    }
+
+    /// The records of the run's review log. A log which can't be read has no records.
+    pub fn review_history(&self) -> Vec<ReviewRecord> {
+        let mut records = self
+            .history_path()
+            .and_then(|path| load_review_log(&path).ok())
+            .unwrap_or_default();
+        records.extend(self.unlogged.iter().cloned());
+
+        records
+    }
+
+    /// Tune the run's scheduler to the reviews recorded so far, leaving out those undone.
+    pub fn fit_scheduler(&mut self, records: &[ReviewRecord]) {
+        let reviews: Vec<Review> = without_undone(records)
+            .iter()
+            .map(|record| record.review())
+            .collect();
+        self.schedule.scheduler.fit(&reviews);
+    }
+}
+
+/// The records of gradings which still stand. A record marked as undone takes out the latest
+/// record before it of the same grading, and a grading redone is logged again.
+pub fn without_undone(records: &[ReviewRecord]) -> Vec<ReviewRecord> {
+    let mut kept: Vec<ReviewRecord> = vec![];
+    for record in records {
+        if !record.undone {
+            kept.push(record.clone());
+        } else if let Some(index) = kept
+            .iter()
+            .rposition(|kept| kept.card_id == record.card_id && kept.timestamp == record.timestamp)
+        {
+            kept.remove(index);
+        }
+    }
+
+    kept
+}
+
+const UNDONE: &str = "undone";
+
+fn grade_name(grade: Grade) -> &'static str {
+    match grade {
+        Grade::Again => "again",
+        Grade::Hard => "hard",
+        Grade::Good => "good",
+        Grade::Easy => "easy",
+    }
+}
+
+fn parse_grade(name: &str) -> Option<Grade> {
+    match name {
+        "again" => Some(Grade::Again),
+        "hard" => Some(Grade::Hard),
+        "good" => Some(Grade::Good),
+        "easy" => Some(Grade::Easy),
+        _ => None,
+    }
+}
+
+/// Records are written one per line with tab separated fields. The time to flip is left empty for
+/// cards which weren't flipped, and records of undone gradings end with an `undone` field.
+impl Display for ReviewRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.timestamp,
+            self.card_id,
+            self.from.replace('\t', " "),
+            self.to.replace('\t', " "),
+            grade_name(self.grade),
+            self.time_to_answer.as_millis(),
+            self.flipped,
+            self.time_to_flip
+                .map_or(String::new(), |time| time.as_millis().to_string())
+        )?;
+
+        if self.undone {
+            write!(f, "\t{UNDONE}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ReviewRecord {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split('\t').collect();
+        if !(7..=9).contains(&fields.len()) {
+            return Err(());
+        }
+        let undone = match fields.get(8) {
+            None => false,
+            Some(&UNDONE) => true,
+            Some(_) => return Err(()),
+        };
+
+        let time_to_flip = match fields.get(7) {
+            Some(millis) if !millis.is_empty() => {
+                Some(Duration::from_millis(millis.parse().map_err(|_| ())?))
+            }
+            _ => None,
+        };
+
+        Ok(ReviewRecord {
+            timestamp: fields[0].parse().map_err(|_| ())?,
+            card_id: fields[1].parse().map_err(|_| ())?,
+            from: fields[2].to_string(),
+            to: fields[3].to_string(),
+            grade: parse_grade(fields[4]).ok_or(())?,
+            time_to_answer: Duration::from_millis(fields[5].parse().map_err(|_| ())?),
+            flipped: fields[6].parse().map_err(|_| ())?,
+            time_to_flip,
+            undone,
+        })
+    }
+}
+
+/// The review log is kept next to the run, so that runs over the same deck keep their own history.
+pub fn review_log_path(run_path: &str) -> String {
+    format!("{run_path}.history")
+}
+
+pub fn append_review(filepath: &str, record: &ReviewRecord) -> Result<(), IzeError> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(filepath)
+        .map_err(|e| IzeError::io(filepath, e))?;
+
+    // Write the whole line at once so that concurrent sessions don't interleave records.
+    file.write_all(format!("{}\n", record).as_bytes())
+        .map_err(|e| IzeError::io(filepath, e))
+}
+
+/// Read every record in the log. A missing log has no records.
+pub fn load_review_log(filepath: &str) -> Result<Vec<ReviewRecord>, IzeError> {
+    if !Path::new(filepath).exists() {
+        return Ok(vec![]);
+    }
+
+    let file = File::open(filepath).map_err(|e| IzeError::io(filepath, e))?;
+
+    let mut records = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| IzeError::io(filepath, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = line.parse().map_err(|_| {
+            IzeError::parse(
+                filepath,
+                index + 1,
+                &line,
+                "Invalid review record".to_string(),
+            )
+        })?;
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(card_id: usize, timestamp: u64) -> ReviewRecord {
+        ReviewRecord {
+            card_id,
+            timestamp,
+            from: "New".to_string(),
+            to: "Box 2".to_string(),
+            grade: Grade::Good,
+            time_to_answer: Duration::from_millis(1500),
+            flipped: false,
+            time_to_flip: None,
+            undone: false,
+        }
+    }
+
+    #[test]
+    fn undone_records_are_read_back() {
+        let undone = ReviewRecord {
+            undone: true,
+            ..record(3, 100)
+        };
+
+        assert_eq!(undone.to_string().parse(), Ok(undone.clone()));
+        assert_eq!(record(3, 100).to_string().parse(), Ok(record(3, 100)));
+        assert!(!record(3, 100).to_string().contains(UNDONE));
+    }
+
+    #[test]
+    fn undone_gradings_are_left_out() {
+        let undo = |record: ReviewRecord| ReviewRecord {
+            undone: true,
+            ..record
+        };
+        let records = [
+            record(1, 100),
+            record(2, 100),
+            undo(record(2, 100)),
+            record(3, 200),
+            undo(record(3, 200)),
+            // Redone.
+            record(3, 200),
+        ];
+
+        assert_eq!(
+            without_undone(&records),
+            vec![record(1, 100), record(3, 200)]
+        );
+    }
+}
@@ -1,32 +1,52 @@
 use std::{error::Error, fmt::Display};
 
-use rand::{seq::SliceRandom, thread_rng};
+use crate::{
+    scheduler::{today, Grade},
+    PracticeRun,
+};
 
-use crate::PracticeRun;
+/// Cards answered wrongly come back after this many other cards, then after the next number of
+/// cards each time they are missed again.
+pub const DEFAULT_LEARNING_STEPS: [usize; 2] = [3, 10];
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum RunCategory {
-    Remaining,
-    Memorized,
-    Working,
-    Incorrect,
+/// Learning steps as numbers separated by spaces or commas, e.g. `3 10`. `none` or an empty text
+/// turns relearning off.
+pub fn parse_learning_steps(text: &str) -> Result<Vec<usize>, String> {
+    if text.trim().eq_ignore_ascii_case("none") {
+        return Ok(vec![]);
+    }
+
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|step| !step.is_empty())
+        .map(|step| match step.parse() {
+            Ok(step) if step > 0 => Ok(step),
+            _ => Err(format!("Learning step '{step}' must be a number above 0.")),
+        })
+        .collect()
 }
 
-impl Into<usize> for RunCategory {
-    fn into(self) -> usize {
-        match self {
-            RunCategory::Remaining => 0,
-            RunCategory::Memorized => 1,
-            RunCategory::Working => 2,
-            RunCategory::Incorrect => 3,
-        }
+/// Learning steps as written in run files, `none` if there are none.
+pub fn format_learning_steps(steps: &[usize]) -> String {
+    if steps.is_empty() {
+        return "none".to_string();
     }
+
+    let steps: Vec<String> = steps.iter().map(|step| step.to_string()).collect();
+    steps.join(" ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunCategory {
+    Remaining,
+    /// Index into the run's boxes.
+    Box(usize),
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum RunActionError {
     IdNotFound(usize, RunCategory),
     ArrayEmpty(RunCategory),
+    CategoryNotFound(RunCategory),
 }
 
 impl Display for RunActionError {
@@ -50,29 +70,62 @@ impl Error for RunActionError {
 }
 
 impl PracticeRun {
-    pub fn category_array(&mut self) -> [&mut Vec<usize>; 4] {
-        [
-            &mut self.remaining,
-            &mut self.memorized,
-            &mut self.working,
-            &mut self.incorrect,
-        ]
+    pub fn category(&self, category: RunCategory) -> Option<&Vec<usize>> {
+        match category {
+            RunCategory::Remaining => Some(&self.remaining),
+            RunCategory::Box(index) => self.boxes.get(index).map(|card_box| &card_box.ids),
+        }
     }
 
-    fn shuffle_vec(vec: &mut Vec<usize>) {
-        vec.shuffle(&mut thread_rng());
+    pub fn category_mut(&mut self, category: RunCategory) -> Option<&mut Vec<usize>> {
+        match category {
+            RunCategory::Remaining => Some(&mut self.remaining),
+            RunCategory::Box(index) => self.boxes.get_mut(index).map(|card_box| &mut card_box.ids),
+        }
     }
 
+    pub fn category_name(&self, category: RunCategory) -> &str {
+        match category {
+            RunCategory::Remaining => "Remaining",
+            RunCategory::Box(index) => self
+                .boxes
+                .get(index)
+                .map_or("Unknown", |card_box| &card_box.name),
+        }
+    }
+
+    /// Every box of the run, from the least known cards to the best known.
+    pub fn box_categories(&self) -> impl Iterator<Item = RunCategory> {
+        (0..self.boxes.len()).map(RunCategory::Box)
+    }
+
+    /// Put the cards of `category` in the run's [`CardOrder`](crate::ordering::CardOrder).
     pub fn shuffle(&mut self, category: RunCategory) {
-        let array = self.category_array();
-        let index: usize = category.into();
+        self.journaled(|run| {
+            let Some(mut ids) = run.category_mut(category).map(std::mem::take) else {
+                return;
+            };
 
-        Self::shuffle_vec(array[index])
+            run.order_ids(&mut ids, category);
+            if let Some(pile) = run.category_mut(category) {
+                *pile = ids;
+            }
+        })
     }
 
     pub fn shuffle_all(&mut self) {
-        let mut array = self.category_array();
-        array.iter_mut().for_each(|v| Self::shuffle_vec(v));
+        self.journaled(|run| {
+            run.shuffle(RunCategory::Remaining);
+            for category in run.box_categories().collect::<Vec<_>>() {
+                run.shuffle(category);
+            }
+        })
+    }
+
+    fn check_category(&self, category: RunCategory) -> Result<(), RunActionError> {
+        self.category(category)
+            .map(|_| ())
+            .ok_or(RunActionError::CategoryNotFound(category))
     }
 
     pub fn move_last(
@@ -80,16 +133,18 @@ impl PracticeRun {
         source_category: RunCategory,
         destination_category: RunCategory,
     ) -> Result<(), RunActionError> {
-        let array = self.category_array();
-        let src_cat: usize = source_category.into();
-        let dest_cat: usize = destination_category.into();
+        self.check_category(destination_category)?;
+
+        self.journaled(|run| {
+            let id = run
+                .category_mut(source_category)
+                .ok_or(RunActionError::CategoryNotFound(source_category))?
+                .pop()
+                .ok_or(RunActionError::ArrayEmpty(source_category))?;
 
-        if let Some(id) = array[src_cat].pop() {
-            array[dest_cat].push(id);
+            run.place(id, source_category, destination_category);
             Ok(())
-        } else {
-            Err(RunActionError::ArrayEmpty(source_category))
-        }
+        })
     }
 
     pub fn move_index(
@@ -98,19 +153,168 @@ impl PracticeRun {
         source_category: RunCategory,
         destination_category: RunCategory,
     ) -> Result<(), RunActionError> {
-        let array = self.category_array();
-        let src_cat: usize = source_category.into();
-        let dest_cat: usize = destination_category.into();
+        self.check_category(destination_category)?;
 
-        if let Some(src_index) = array[src_cat].iter().position(|_id| *_id == id) {
-            array[src_cat].remove(src_index);
+        self.journaled(|run| {
+            let source = run
+                .category_mut(source_category)
+                .ok_or(RunActionError::CategoryNotFound(source_category))?;
+
+            if let Some(src_index) = source.iter().position(|_id| *_id == id) {
+                source.remove(src_index);
+            } else {
+                return Err(RunActionError::IdNotFound(id, source_category));
+            }
+
+            run.place(id, source_category, destination_category);
+
+            Ok(())
+        })
+    }
+
+    /// Add a card which was taken out of `source_category` to `destination_category`.
+    fn place(
+        &mut self,
+        id: usize,
+        source_category: RunCategory,
+        destination_category: RunCategory,
+    ) {
+        if source_category == RunCategory::Remaining {
+            if let RunCategory::Box(_) = destination_category {
+                self.last_graded = Some(id);
+            }
+
+            if self.requeue(id, destination_category) {
+                return;
+            }
+
+            // The card was graded when it was first missed, and now leaves the remaining pile.
+            if self.relearning.remove(&id).is_some() {
+                self.update_origin(id, source_category, destination_category);
+                if let Some(destination) = self.category_mut(destination_category) {
+                    destination.push(id);
+                }
+                return;
+            }
+        }
+
+        self.record_grade(id, source_category, destination_category);
+        self.update_origin(id, source_category, destination_category);
+
+        if let Some(destination) = self.category_mut(destination_category) {
+            destination.push(id);
+        }
+    }
+
+    /// Put a card answered wrongly back into the remaining pile a few cards from the top, as set
+    /// by the learning steps, until it is answered correctly. Returns false if the card isn't
+    /// being relearned, and so goes to `destination_category` as usual.
+    fn requeue(&mut self, id: usize, destination_category: RunCategory) -> bool {
+        if self.learning_steps.is_empty()
+            || self.grade(id, destination_category) != Some(Grade::Again)
+        {
+            return false;
+        }
+
+        let step = match self.relearning.get(&id) {
+            Some(step) => *step,
+            None => {
+                self.record_grade(id, RunCategory::Remaining, destination_category);
+                0
+            }
+        };
+        self.relearning.insert(id, step + 1);
+
+        let depth = self.learning_steps[step.min(self.learning_steps.len() - 1)];
+        self.remaining
+            .insert(self.remaining.len().saturating_sub(depth), id);
+
+        true
+    }
+
+    /// The number of cards in the remaining pile which were answered wrongly and came back.
+    pub fn relearning_count(&self) -> usize {
+        self.remaining
+            .iter()
+            .filter(|id| self.relearning.contains_key(id))
+            .count()
+    }
+
+    fn update_origin(
+        &mut self,
+        id: usize,
+        source_category: RunCategory,
+        destination_category: RunCategory,
+    ) {
+        match (source_category, destination_category) {
+            (RunCategory::Box(index), RunCategory::Remaining) => {
+                self.origins.insert(id, index);
+            }
+            (_, RunCategory::Box(_)) => {
+                self.origins.remove(&id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Cards sorted out of the remaining pile have been answered, so update their schedule.
+    fn record_grade(
+        &mut self,
+        id: usize,
+        source_category: RunCategory,
+        destination_category: RunCategory,
+    ) {
+        if source_category != RunCategory::Remaining {
+            return;
+        }
+
+        if let Some(grade) = self.grade(id, destination_category) {
+            self.journal_schedule(id);
+            self.schedule.review(id, grade, today());
+        }
+    }
+
+    /// The box a card in the remaining pile was taken from. New cards start in the first box.
+    pub fn origin(&self, id: usize) -> usize {
+        self.origins.get(&id).copied().unwrap_or(0)
+    }
+
+    /// The grade a card in the remaining pile receives when it is sorted into
+    /// `destination_category`.
+    pub fn grade(&self, id: usize, destination_category: RunCategory) -> Option<Grade> {
+        let RunCategory::Box(index) = destination_category else {
+            return None;
+        };
+
+        if index == 0 {
+            Some(Grade::Again)
+        } else if index + 1 == self.boxes.len() || index > self.origin(id) {
+            Some(Grade::Good)
         } else {
-            return Err(RunActionError::IdNotFound(id, source_category));
+            Some(Grade::Hard)
         }
+    }
+
+    /// Where a card in the remaining pile goes when it is answered correctly.
+    pub fn promotion_target(&self, id: usize) -> RunCategory {
+        let origin = self.origin(id);
+        RunCategory::Box(self.boxes.get(origin).map_or(origin, |b| b.promote_to))
+    }
+
+    /// Where a card in the remaining pile goes when it is answered incorrectly.
+    pub fn demotion_target(&self, id: usize) -> RunCategory {
+        let origin = self.origin(id);
+        RunCategory::Box(self.boxes.get(origin).map_or(0, |b| b.demote_to))
+    }
 
-        array[dest_cat].push(id);
+    pub fn promote(&mut self, id: usize) -> Result<(), RunActionError> {
+        let destination = self.promotion_target(id);
+        self.move_index(id, RunCategory::Remaining, destination)
+    }
 
-        Ok(())
+    pub fn demote(&mut self, id: usize) -> Result<(), RunActionError> {
+        let destination = self.demotion_target(id);
+        self.move_index(id, RunCategory::Remaining, destination)
     }
 
     pub fn move_category(
@@ -118,27 +322,219 @@ impl PracticeRun {
         source_category: RunCategory,
         destination_category: RunCategory,
     ) {
-        let array = self.category_array();
-        let src_cat: usize = source_category.into();
-        let dest_cat: usize = destination_category.into();
+        if self.check_category(destination_category).is_err() {
+            return;
+        }
+
+        self.journaled(|run| {
+            let src: Vec<usize> = match run.category_mut(source_category) {
+                Some(source) => std::mem::take(source),
+                None => return,
+            };
 
-        let mut src: Vec<usize> = array[src_cat].drain(..).collect();
+            for id in src.iter() {
+                run.update_origin(*id, source_category, destination_category);
+            }
 
-        array[dest_cat].append(&mut src);
+            if let Some(destination) = run.category_mut(destination_category) {
+                destination.extend(src);
+            }
+        })
     }
 
-    pub fn reset(&mut self)
-    {
-        self.move_category(RunCategory::Incorrect, RunCategory::Remaining);
-        self.move_category(RunCategory::Memorized, RunCategory::Remaining);
-        self.move_category(RunCategory::Working, RunCategory::Remaining);
+    pub fn reset(&mut self) {
+        self.journaled(|run| {
+            for category in run.box_categories().collect::<Vec<_>>() {
+                run.move_category(category, RunCategory::Remaining);
+            }
+
+            run.shuffle(RunCategory::Remaining);
+        })
+    }
+
+    /// Boxes which are due in the next session.
+    pub fn next_session_boxes(&self) -> Vec<RunCategory> {
+        self.box_categories()
+            .filter(|category| match category {
+                RunCategory::Box(index) => self.boxes[*index].is_due(self.session + 1),
+                RunCategory::Remaining => false,
+            })
+            .collect()
+    }
+
+    /// The number of cards which will be reviewed in the next session.
+    pub fn next_session_count(&self) -> usize {
+        self.next_session_boxes()
+            .into_iter()
+            .filter_map(|category| self.category(category))
+            .map(|ids| ids.len())
+            .sum()
+    }
+
+    /// Move the cards of every box due in the next session into the remaining pile.
+    pub fn start_session(&mut self) {
+        self.journaled(|run| {
+            for category in run.next_session_boxes() {
+                run.move_category(category, RunCategory::Remaining);
+            }
+
+            run.session += 1;
+            run.shuffle(RunCategory::Remaining);
+        })
+    }
+
+    /// Move every card which is due on `day` into the remaining pile.
+    pub fn queue_due(&mut self, day: u64) {
+        self.journaled(|run| {
+            let schedule = &run.schedule;
+            let mut due = vec![];
+            for (index, card_box) in run.boxes.iter_mut().enumerate() {
+                card_box.ids.retain(|id| {
+                    let is_due = schedule.is_due(*id, day);
+                    if is_due {
+                        due.push((*id, index));
+                    }
+                    !is_due
+                });
+            }
+
+            for (id, index) in due {
+                run.origins.insert(id, index);
+                run.remaining.push(id);
+            }
+            run.shuffle(RunCategory::Remaining);
+        })
+    }
 
-        self.shuffle(RunCategory::Remaining);
+    /// The number of cards outside of the remaining pile which are due on `day`.
+    pub fn due_count(&self, day: u64) -> usize {
+        self.boxes
+            .iter()
+            .flat_map(|card_box| card_box.ids.iter())
+            .filter(|id| self.schedule.is_due(**id, day))
+            .count()
     }
 
     pub fn skip(&mut self) {
-        if let Some(element) = self.remaining.pop() {
-            self.remaining.insert(0, element);
+        self.journaled(|run| {
+            if let Some(element) = run.remaining.pop() {
+                run.remaining.insert(0, element);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{leitner::BoxLayout, sm2::Sm2, Card, Deck};
+
+    fn run(cards: usize) -> PracticeRun {
+        let mut deck = Deck::new();
+        for card_id in 1..=cards {
+            deck.cards.insert(
+                card_id,
+                Card {
+                    card_id,
+                    front: format!("front {card_id}"),
+                    back: format!("back {card_id}"),
+                    direction: None,
+                    tags: vec![],
+                },
+            );
+        }
+
+        PracticeRun::new_from_deck(&deck, Box::new(Sm2), BoxLayout::Leitner(3).boxes()).with_seed(5)
+    }
+
+    /// Answer the card on top of the remaining pile wrongly, returning it.
+    fn miss(run: &mut PracticeRun) -> usize {
+        let id = *run.remaining.last().unwrap();
+        run.move_last(RunCategory::Remaining, RunCategory::Box(0))
+            .unwrap();
+        id
+    }
+
+    /// How many cards are shown before `id`.
+    fn depth(run: &PracticeRun, id: usize) -> usize {
+        let position = run.remaining.iter().position(|other| *other == id).unwrap();
+        run.remaining.len() - 1 - position
+    }
+
+    #[test]
+    fn missed_cards_come_back_after_each_learning_step() {
+        let mut run = run(20);
+        let id = miss(&mut run);
+        assert_eq!(depth(&run, id), 3);
+        let schedule = run.schedule.cards[&id];
+
+        for _ in 0..3 {
+            run.move_last(RunCategory::Remaining, RunCategory::Box(1))
+                .unwrap();
+        }
+        assert_eq!(miss(&mut run), id);
+        assert_eq!(depth(&run, id), 10);
+        // Missing it again while relearning doesn't grade it again.
+        assert_eq!(run.schedule.cards[&id], schedule);
+        assert_eq!(run.relearning_count(), 1);
+    }
+
+    #[test]
+    fn cards_missed_past_the_last_step_keep_coming_back_after_it() {
+        let mut run = run(20);
+        run.learning_steps = vec![1, 2];
+        let id = miss(&mut run);
+
+        for expected in [2, 2] {
+            while run.remaining.last() != Some(&id) {
+                run.move_last(RunCategory::Remaining, RunCategory::Box(1))
+                    .unwrap();
+            }
+            miss(&mut run);
+            assert_eq!(depth(&run, id), expected);
         }
     }
+
+    #[test]
+    fn near_the_end_of_the_pile_missed_cards_go_to_the_bottom() {
+        let mut run = run(3);
+        let id = miss(&mut run);
+
+        assert_eq!(run.remaining.first(), Some(&id));
+        assert_eq!(depth(&run, id), 2);
+    }
+
+    #[test]
+    fn relearned_cards_leave_the_pile_without_being_graded_again() {
+        let mut run = run(1);
+        let id = miss(&mut run);
+        let schedule = run.schedule.cards[&id];
+
+        run.move_last(RunCategory::Remaining, RunCategory::Box(2))
+            .unwrap();
+
+        assert!(run.remaining.is_empty());
+        assert_eq!(run.boxes[2].ids, vec![id]);
+        assert_eq!(run.schedule.cards[&id], schedule);
+        assert_eq!(run.relearning_count(), 0);
+    }
+
+    #[test]
+    fn without_learning_steps_missed_cards_go_to_the_first_box() {
+        let mut run = run(5);
+        run.learning_steps = vec![];
+        let id = miss(&mut run);
+
+        assert_eq!(run.boxes[0].ids, vec![id]);
+        assert!(!run.remaining.contains(&id));
+    }
+
+    #[test]
+    fn learning_steps_are_read_as_written() {
+        assert_eq!(parse_learning_steps("3, 10").unwrap(), vec![3, 10]);
+        assert_eq!(parse_learning_steps("none").unwrap(), Vec::<usize>::new());
+        assert!(parse_learning_steps("3 0").is_err());
+        assert_eq!(format_learning_steps(&[]), "none");
+        assert_eq!(format_learning_steps(&[3, 10]), "3 10");
+    }
 }
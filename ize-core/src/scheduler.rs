@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{Debug, Display},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{fsrs::Fsrs, sm2::Sm2};
+
+pub(crate) const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+
+const DEFAULT_EASE: f64 = 2.5;
+
+/// The number of whole days since the unix epoch.
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or(0)
+}
+
+/// How well a card was recalled when it was graded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl Grade {
+    pub fn is_success(self) -> bool {
+        self != Grade::Again
+    }
+}
+
+/// A single grading of a card, used to fit scheduler parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Review {
+    pub card_id: usize,
+    pub day: u64,
+    pub grade: Grade,
+}
+
+/// Review state of a single card.
+///
+/// Fields are shared between the scheduling algorithms. Each algorithm only uses the ones it needs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CardSchedule {
+    pub ease: f64,
+    /// Days until the next review.
+    pub interval: u64,
+    /// Successful reviews in a row.
+    pub repetitions: u32,
+    pub lapses: u32,
+    /// Day on which the card should next be reviewed.
+    pub due: u64,
+    /// Days until recall probability falls to 90%.
+    pub stability: f64,
+    /// From 1 (easy) to 10 (hard).
+    pub difficulty: f64,
+    pub last_review: Option<u64>,
+}
+
+impl Default for CardSchedule {
+    fn default() -> Self {
+        CardSchedule {
+            ease: DEFAULT_EASE,
+            interval: 0,
+            repetitions: 0,
+            lapses: 0,
+            due: 0,
+            stability: 0.0,
+            difficulty: 0.0,
+            last_review: None,
+        }
+    }
+}
+
+impl CardSchedule {
+    pub fn is_new(&self) -> bool {
+        self.last_review.is_none()
+    }
+
+    pub fn elapsed_days(&self, today: u64) -> u64 {
+        self.last_review
+            .map_or(0, |last_review| today.saturating_sub(last_review))
+    }
+}
+
+pub trait Scheduler: Debug {
+    /// Update the state of a card which was graded on `today`.
+    fn review(&self, card: &mut CardSchedule, grade: Grade, today: u64);
+
+    /// Predicted probability that the card is recalled on `today`.
+    fn retrievability(&self, card: &CardSchedule, today: u64) -> f64;
+
+    /// Tune the scheduler's parameters from previous reviews.
+    fn fit(&mut self, _reviews: &[Review]) {}
+
+    /// Single line written at the top of the schedule file identifying the algorithm and its
+    /// parameters.
+    fn header(&self) -> String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sm2,
+    Fsrs,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 2] = [Algorithm::Sm2, Algorithm::Fsrs];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sm2 => "SM-2",
+            Algorithm::Fsrs => "FSRS",
+        }
+    }
+
+    pub fn scheduler(self) -> Box<dyn Scheduler> {
+        match self {
+            Algorithm::Sm2 => Box::new(Sm2),
+            Algorithm::Fsrs => Box::new(Fsrs::default()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ScheduleParseError {
+    line: String,
+}
+
+impl ScheduleParseError {
+    pub(crate) fn new(line: &str) -> Self {
+        ScheduleParseError {
+            line: line.to_string(),
+        }
+    }
+}
+
+impl Display for ScheduleParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid schedule entry '{}'", self.line)
+    }
+}
+
+impl Error for ScheduleParseError {}
+
+/// Parse the header line written by [`Scheduler::header`].
+pub fn parse_scheduler(header: &str) -> Result<Box<dyn Scheduler>, ScheduleParseError> {
+    let (name, parameters) = header.split_once(' ').unwrap_or((header, ""));
+
+    match name {
+        "sm2" => Ok(Box::new(Sm2)),
+        "fsrs" => Ok(Box::new(parameters.parse::<Fsrs>()?)),
+        _ => Err(ScheduleParseError::new(header)),
+    }
+}
+
+impl Display for CardSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "due={} interval={} ease={:.2} repetitions={} lapses={} stability={:.4} difficulty={:.4}",
+            self.due,
+            self.interval,
+            self.ease,
+            self.repetitions,
+            self.lapses,
+            self.stability,
+            self.difficulty
+        )?;
+
+        if let Some(last_review) = self.last_review {
+            write!(f, " last_review={}", last_review)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for CardSchedule {
+    type Err = ScheduleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ScheduleParseError::new(s);
+
+        let mut schedule = CardSchedule::default();
+        for field in s.split_whitespace() {
+            let (key, value) = field.split_once('=').ok_or_else(error)?;
+            match key {
+                "due" => schedule.due = value.parse().map_err(|_| error())?,
+                "interval" => schedule.interval = value.parse().map_err(|_| error())?,
+                "ease" => schedule.ease = value.parse().map_err(|_| error())?,
+                "repetitions" => schedule.repetitions = value.parse().map_err(|_| error())?,
+                "lapses" => schedule.lapses = value.parse().map_err(|_| error())?,
+                "stability" => schedule.stability = value.parse().map_err(|_| error())?,
+                "difficulty" => schedule.difficulty = value.parse().map_err(|_| error())?,
+                "last_review" => schedule.last_review = Some(value.parse().map_err(|_| error())?),
+                // Ignore unknown fields so that newer files can still be read.
+                _ => {}
+            }
+        }
+
+        Ok(schedule)
+    }
+}
+
+/// Per card review state used to decide which cards need practice on a given day.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Schedule {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_io::scheduler_header"))]
+    pub scheduler: Box<dyn Scheduler>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_io::id_map"))]
+    pub cards: HashMap<usize, CardSchedule>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new(Box::new(Sm2))
+    }
+}
+
+impl Schedule {
+    pub fn new(scheduler: Box<dyn Scheduler>) -> Self {
+        Schedule {
+            scheduler,
+            cards: HashMap::new(),
+        }
+    }
+
+    pub fn review(&mut self, card_id: usize, grade: Grade, today: u64) {
+        let card = self.cards.entry(card_id).or_default();
+        self.scheduler.review(card, grade, today);
+    }
+
+    /// Cards that have never been reviewed are always due.
+    pub fn is_due(&self, card_id: usize, today: u64) -> bool {
+        self.cards
+            .get(&card_id)
+            .is_none_or(|schedule| schedule.due <= today)
+    }
+
+    /// The probability of recalling a card, or `None` if it has never been reviewed.
+    pub fn retrievability(&self, card_id: usize, today: u64) -> Option<f64> {
+        self.cards
+            .get(&card_id)
+            .filter(|schedule| !schedule.is_new())
+            .map(|schedule| self.scheduler.retrievability(schedule, today))
+    }
+
+    pub fn remove(&mut self, card_id: usize) {
+        self.cards.remove(&card_id);
+    }
+}
@@ -0,0 +1,405 @@
+//! Decks and runs as JSON, TOML or YAML documents, picked by the file's extension.
+//!
+//! Item ids are written as text, like `12:reverse` in run files, since TOML only allows string
+//! keys and its integers are signed, leaving no room for the reverse flag in the top bit.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    card_ids::{CardIdError, IdScheme, ReadCard},
+    error::IzeError,
+    item::{format_item_id, parse_item_id, Direction},
+    scheduler::{parse_scheduler, Scheduler},
+    Card, Deck,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DataFormat {
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+impl DataFormat {
+    pub(crate) fn for_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+
+        match extension.as_str() {
+            "json" => Some(DataFormat::Json),
+            #[cfg(feature = "toml")]
+            "toml" => Some(DataFormat::Toml),
+            #[cfg(feature = "yaml")]
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// The error's message without the location the parser appends to it.
+fn without_location(message: String, line: usize, column: usize) -> String {
+    match message.strip_suffix(&format!(" at line {line} column {column}")) {
+        Some(message) => message.to_string(),
+        None => message,
+    }
+}
+
+pub(crate) fn read<T: DeserializeOwned>(path: &str, format: DataFormat) -> Result<T, IzeError> {
+    let text = fs::read_to_string(path).map_err(|e| IzeError::io(path, e))?;
+
+    match format {
+        DataFormat::Json => serde_json::from_str(&text).map_err(|e| {
+            let message = without_location(e.to_string(), e.line(), e.column());
+            IzeError::parse(path, e.line(), "", message).at_column(e.column())
+        }),
+        #[cfg(feature = "toml")]
+        DataFormat::Toml => toml::from_str(&text).map_err(|e| {
+            let (line, column) = e.span().map_or((0, 0), |span| {
+                crate::error::line_and_column(text.as_bytes(), span.start)
+            });
+            IzeError::parse(path, line, "", e.message().to_string()).at_column(column)
+        }),
+        #[cfg(feature = "yaml")]
+        DataFormat::Yaml => serde_yaml::from_str(&text).map_err(|e| {
+            let (line, column) = e
+                .location()
+                .map_or((0, 0), |location| (location.line(), location.column()));
+            let message = without_location(e.to_string(), line, column);
+            IzeError::parse(path, line, "", message).at_column(column)
+        }),
+    }
+}
+
+pub(crate) fn write<T: Serialize>(
+    w: &mut impl Write,
+    value: &T,
+    format: DataFormat,
+) -> io::Result<()> {
+    let text = match format {
+        DataFormat::Json => serde_json::to_string_pretty(value).map_err(io::Error::other)?,
+        #[cfg(feature = "toml")]
+        DataFormat::Toml => toml::to_string_pretty(value).map_err(io::Error::other)?,
+        #[cfg(feature = "yaml")]
+        DataFormat::Yaml => serde_yaml::to_string(value).map_err(io::Error::other)?,
+    };
+
+    writeln!(w, "{}", text.trim_end())
+}
+
+/// A deck's cards as a list ordered by id, since each card already holds its id.
+pub(crate) fn card_list<S: Serializer>(
+    cards: &HashMap<usize, Card>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut cards: Vec<&Card> = cards.values().collect();
+    cards.sort_by_key(|card| card.card_id);
+    cards.serialize(serializer)
+}
+
+/// A deck as written in the file, where cards may leave out their ids.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct DeckFile {
+    title: Option<String>,
+    direction: Direction,
+    id_scheme: IdScheme,
+    cards: Vec<CardFile>,
+}
+
+#[derive(Deserialize)]
+struct CardFile {
+    #[serde(default)]
+    card_id: Option<usize>,
+    front: String,
+    back: String,
+    #[serde(default)]
+    direction: Option<Direction>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl DeckFile {
+    /// The deck with ids given to cards without one, following `id_scheme` if given and otherwise
+    /// the deck's own scheme.
+    pub(crate) fn into_deck(self, id_scheme: Option<IdScheme>) -> Result<Deck, CardIdError> {
+        let mut deck = Deck {
+            title: self.title,
+            direction: self.direction,
+            id_scheme: id_scheme.unwrap_or(self.id_scheme),
+            cards: HashMap::new(),
+            generated_ids: 0,
+        };
+
+        let cards = self
+            .cards
+            .into_iter()
+            .map(|card| ReadCard {
+                card_id: card.card_id,
+                line: 0,
+                card: Card {
+                    card_id: 0,
+                    front: card.front,
+                    back: card.back,
+                    direction: card.direction,
+                    tags: card.tags,
+                },
+            })
+            .collect();
+        deck.add_read_cards(cards)?;
+
+        Ok(deck)
+    }
+}
+
+impl TryFrom<DeckFile> for Deck {
+    type Error = CardIdError;
+
+    fn try_from(file: DeckFile) -> Result<Self, Self::Error> {
+        file.into_deck(None)
+    }
+}
+
+/// An item id as written in the file. Plain numbers are read too, as written by earlier versions.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ItemIdText {
+    Number(usize),
+    Text(String),
+}
+
+impl ItemIdText {
+    fn into_id<E: serde::de::Error>(self) -> Result<usize, E> {
+        match self {
+            ItemIdText::Number(id) => Ok(id),
+            ItemIdText::Text(text) => parse_item_id(&text).ok_or_else(|| {
+                E::custom(format!("Item id '{text}' must look like 12 or 12:reverse."))
+            }),
+        }
+    }
+}
+
+/// A list of item ids, kept in order.
+pub(crate) mod item_ids {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(ids: &[usize], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(ids.iter().map(|id| format_item_id(*id)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<usize>, D::Error> {
+        Vec::<ItemIdText>::deserialize(deserializer)?
+            .into_iter()
+            .map(ItemIdText::into_id)
+            .collect()
+    }
+}
+
+/// An item id which may be missing.
+pub(crate) mod optional_item_id {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(id: &Option<usize>, serializer: S) -> Result<S::Ok, S::Error> {
+        match id {
+            Some(id) => serializer.serialize_some(&format_item_id(*id)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<usize>, D::Error> {
+        Option::<ItemIdText>::deserialize(deserializer)?
+            .map(ItemIdText::into_id)
+            .transpose()
+    }
+}
+
+/// A map keyed by item id, written in id order.
+pub(crate) mod id_map {
+    use super::*;
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &HashMap<usize, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let sorted: BTreeMap<&usize, &V> = map.iter().collect();
+        serializer.collect_map(
+            sorted
+                .into_iter()
+                .map(|(id, value)| (format_item_id(*id), value)),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<usize, V>, D::Error> {
+        let map = HashMap::<String, V>::deserialize(deserializer)?;
+
+        map.into_iter()
+            .map(|(id, value)| Ok((ItemIdText::Text(id).into_id()?, value)))
+            .collect()
+    }
+}
+
+/// The scheduler as the header line of the schedule file, e.g. `sm2`.
+pub(crate) mod scheduler_header {
+    use super::*;
+
+    // serde hands over a reference to the field itself.
+    #[allow(clippy::borrowed_box)]
+    pub fn serialize<S: Serializer>(
+        scheduler: &Box<dyn Scheduler>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&scheduler.header())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<dyn Scheduler>, D::Error> {
+        let header = String::deserialize(deserializer)?;
+        parse_scheduler(&header).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        deck_io::{load_deck, load_practice_run, save_deck, save_practice_run},
+        item::{split_item_id, ItemKind},
+        leitner::BoxLayout,
+        ordering::CardOrder,
+        run_actions::RunCategory,
+        sm2::Sm2,
+        PracticeRun,
+    };
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ize-serde-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn path_in(dir: &Path, file: &str) -> String {
+        dir.join(file).to_string_lossy().to_string()
+    }
+
+    fn verbs() -> Deck {
+        let mut deck = Deck::new();
+        deck.title = Some("Verbs".to_string());
+        deck.direction = Direction::Both;
+        let cards = [
+            (1, "go", "went\ngone", None, vec!["irregular"]),
+            (2, "walk", "walked", Some(Direction::Forward), vec![]),
+            (3, "say", "said", None, vec!["irregular", "verbs"]),
+        ];
+        for (card_id, front, back, direction, tags) in cards {
+            let tags = tags.into_iter().map(str::to_string).collect();
+            deck.cards.insert(
+                card_id,
+                Card {
+                    card_id,
+                    front: front.to_string(),
+                    back: back.to_string(),
+                    direction,
+                    tags,
+                },
+            );
+        }
+
+        deck
+    }
+
+    fn fields(deck: &Deck) -> BTreeMap<usize, (String, String, Option<Direction>, Vec<String>)> {
+        deck.cards
+            .values()
+            .map(|card| {
+                let fields = (
+                    card.front.clone(),
+                    card.back.clone(),
+                    card.direction,
+                    card.tags.clone(),
+                );
+                (card.card_id, fields)
+            })
+            .collect()
+    }
+
+    fn assert_deck_round_trips(file: &str) {
+        let dir = test_dir(&format!("deck-{file}"));
+        let path = path_in(&dir, file);
+        let deck = verbs();
+
+        save_deck(&path, &deck).unwrap();
+        let loaded = load_deck(&path).unwrap();
+
+        assert_eq!(loaded.title, deck.title, "{file}");
+        assert_eq!(loaded.direction, deck.direction, "{file}");
+        assert_eq!(fields(&loaded), fields(&deck), "{file}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn assert_run_round_trips(file: &str) {
+        let dir = test_dir(&format!("run-{file}"));
+        let deck_path = path_in(&dir, "deck.txt");
+        let path = path_in(&dir, file);
+        let deck = verbs();
+        save_deck(&deck_path, &deck).unwrap();
+        let mut run =
+            PracticeRun::new_from_deck(&deck, Box::new(Sm2), BoxLayout::Leitner(3).boxes())
+                .with_seed(7);
+        run.set_deck_paths(&[deck_path]);
+        run.order = CardOrder::Weighted;
+        run.move_last(RunCategory::Remaining, RunCategory::Box(1))
+            .unwrap();
+        run.move_last(RunCategory::Remaining, RunCategory::Box(0))
+            .unwrap();
+        // Reverse ids have the top bit set, which TOML's signed integers can't hold.
+        assert!(run
+            .remaining
+            .iter()
+            .any(|id| split_item_id(*id).1 == ItemKind::Reverse));
+
+        save_practice_run(&path, &mut run).unwrap();
+        let (loaded, _) = load_practice_run(&path).unwrap();
+
+        assert_eq!(loaded.remaining, run.remaining, "{file}");
+        assert_eq!(loaded.boxes, run.boxes, "{file}");
+        assert_eq!(loaded.origins, run.origins, "{file}");
+        assert_eq!(loaded.order, run.order, "{file}");
+        assert_eq!(loaded.seed(), run.seed(), "{file}");
+        assert_eq!(loaded.last_graded, run.last_graded, "{file}");
+        assert_eq!(loaded.schedule.cards, run.schedule.cards, "{file}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn json_files_round_trip() {
+        assert_deck_round_trips("deck.json");
+        assert_run_round_trips("study.json");
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_files_round_trip() {
+        assert_deck_round_trips("deck.toml");
+        assert_run_round_trips("study.toml");
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_files_round_trip() {
+        assert_deck_round_trips("deck.yaml");
+        assert_run_round_trips("study.yaml");
+    }
+}
@@ -0,0 +1,146 @@
+//! Sitting down to practice: how long it has gone on, when it should stop, and what was done.
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use crate::review_log::ReviewRecord;
+
+/// When a session ends by itself. Without limits it goes on until the learner stops.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SessionLimits {
+    pub time_limit: Option<Duration>,
+    /// The most cards graded in the session.
+    pub max_cards: Option<usize>,
+}
+
+/// The gradings of one session. Gradings which were undone are still counted, as they are in the
+/// review log.
+#[derive(Debug, Clone)]
+pub struct PracticeSession {
+    started: Instant,
+    pub limits: SessionLimits,
+    reviews: Vec<ReviewRecord>,
+}
+
+impl PracticeSession {
+    pub fn start(limits: SessionLimits) -> Self {
+        PracticeSession {
+            started: Instant::now(),
+            limits,
+            reviews: vec![],
+        }
+    }
+
+    pub fn record(&mut self, record: &ReviewRecord) {
+        self.reviews.push(record.clone());
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Time until the time limit, if there is one.
+    pub fn time_left(&self) -> Option<Duration> {
+        self.limits
+            .time_limit
+            .map(|limit| limit.saturating_sub(self.elapsed()))
+    }
+
+    pub fn reviewed(&self) -> usize {
+        self.reviews.len()
+    }
+
+    /// True once either limit has been reached. The card being answered when time runs out is
+    /// left to be graded, so the front ends check this between cards.
+    pub fn is_over(&self) -> bool {
+        self.time_left().is_some_and(|left| left.is_zero())
+            || self
+                .limits
+                .max_cards
+                .is_some_and(|max_cards| self.reviewed() >= max_cards)
+    }
+
+    pub fn summary(&self) -> SessionSummary {
+        let flip_times: Vec<Duration> = self
+            .reviews
+            .iter()
+            .filter_map(|record| record.time_to_flip)
+            .collect();
+        let answer_times: Vec<Duration> = self
+            .reviews
+            .iter()
+            .map(|record| record.time_to_answer)
+            .collect();
+
+        SessionSummary {
+            reviewed: self.reviewed(),
+            correct: self
+                .reviews
+                .iter()
+                .filter(|record| record.grade.is_success())
+                .count(),
+            elapsed: self.elapsed(),
+            mean_time_to_flip: mean(&flip_times),
+            mean_time_to_answer: mean(&answer_times),
+            slowest_answer: answer_times.iter().max().copied(),
+        }
+    }
+}
+
+fn mean(durations: &[Duration]) -> Option<Duration> {
+    let count = u32::try_from(durations.len())
+        .ok()
+        .filter(|count| *count > 0)?;
+    Some(durations.iter().sum::<Duration>() / count)
+}
+
+/// A duration as `m:ss`, or `h:mm:ss` from an hour on.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionSummary {
+    pub reviewed: usize,
+    /// Gradings other than `Grade::Again`.
+    pub correct: usize,
+    pub elapsed: Duration,
+    pub mean_time_to_flip: Option<Duration>,
+    /// Mean time from a card being shown to it being graded.
+    pub mean_time_to_answer: Option<Duration>,
+    pub slowest_answer: Option<Duration>,
+}
+
+/// One line per figure, leaving out the times if no card was graded.
+impl Display for SessionSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Cards reviewed: {} ({} correct)",
+            self.reviewed, self.correct
+        )?;
+        write!(f, "Session time: {}", format_duration(self.elapsed))?;
+
+        let seconds = |duration: Duration| format!("{:.1}s", duration.as_secs_f64());
+        if let Some(time) = self.mean_time_to_flip {
+            write!(f, "\nAverage time to flip: {}", seconds(time))?;
+        }
+        if let Some(time) = self.mean_time_to_answer {
+            write!(f, "\nAverage time to grade: {}", seconds(time))?;
+        }
+        if let Some(time) = self.slowest_answer {
+            write!(f, "\nSlowest card: {}", seconds(time))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use crate::scheduler::{CardSchedule, Grade, Scheduler};
+
+const MINIMUM_EASE: f64 = 1.3;
+
+/// The classic SuperMemo 2 algorithm.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sm2;
+
+/// Response quality on the 0-5 scale used by SM-2.
+fn quality(grade: Grade) -> f64 {
+    match grade {
+        Grade::Again => 1.0,
+        Grade::Hard => 3.0,
+        Grade::Good => 4.0,
+        Grade::Easy => 5.0,
+    }
+}
+
+impl Scheduler for Sm2 {
+    fn review(&self, card: &mut CardSchedule, grade: Grade, today: u64) {
+        let quality = quality(grade);
+
+        if quality < 3.0 {
+            card.repetitions = 0;
+            card.lapses += 1;
+            card.interval = 1;
+        } else {
+            card.repetitions += 1;
+            card.interval = match card.repetitions {
+                1 => 1,
+                2 => 6,
+                _ => (card.interval as f64 * card.ease).round() as u64,
+            };
+        }
+
+        let miss = 5.0 - quality;
+        card.ease = (card.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MINIMUM_EASE);
+        card.due = today + card.interval;
+        card.last_review = Some(today);
+    }
+
+    /// SM-2 has no memory model, so assume recall falls to 90% by the end of each interval.
+    fn retrievability(&self, card: &CardSchedule, today: u64) -> f64 {
+        let interval = card.interval.max(1) as f64;
+        0.9f64.powf(card.elapsed_days(today) as f64 / interval)
+    }
+
+    fn header(&self) -> String {
+        "sm2".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(card: &mut CardSchedule, grade: Grade, today: u64) {
+        Sm2.review(card, grade, today);
+    }
+
+    #[test]
+    fn intervals_grow_by_ease_after_the_second_review() {
+        let mut card = CardSchedule::default();
+        let mut intervals = vec![];
+        for day in [0, 1, 7] {
+            review(&mut card, Grade::Good, day);
+            intervals.push(card.interval);
+        }
+
+        assert_eq!(intervals, vec![1, 6, 15]);
+        assert_eq!(card.ease, 2.5);
+        assert_eq!(card.due, 22);
+        assert_eq!(card.last_review, Some(7));
+    }
+
+    #[test]
+    fn a_lapse_starts_the_card_over() {
+        let mut card = CardSchedule::default();
+        review(&mut card, Grade::Good, 0);
+        review(&mut card, Grade::Good, 1);
+        review(&mut card, Grade::Again, 7);
+
+        assert_eq!(card.interval, 1);
+        assert_eq!(card.repetitions, 0);
+        assert_eq!(card.lapses, 1);
+        assert_eq!(card.due, 8);
+        assert!((card.ease - 1.96).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ease_rises_on_easy_and_stops_at_the_minimum() {
+        let mut card = CardSchedule::default();
+        review(&mut card, Grade::Easy, 0);
+        assert!((card.ease - 2.6).abs() < 1e-9);
+
+        for day in 1..10 {
+            review(&mut card, Grade::Again, day);
+        }
+        assert_eq!(card.ease, MINIMUM_EASE);
+    }
+}
@@ -0,0 +1,198 @@
+//! Tags on cards, and filters which pick the cards of a run by their tags.
+//!
+//! Filters are written as tags joined by `AND` or `OR`, with `NOT tag` or `-tag` for tags to
+//! leave out, e.g. `verbs AND irregular` or `chapter-3 OR chapter-4 NOT draft`. Tags are compared
+//! ignoring case.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{Card, Deck};
+
+const AND: &str = "AND";
+const OR: &str = "OR";
+const NOT: &str = "NOT";
+
+/// Tags separated by spaces or commas, without repeats.
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = vec![];
+    for tag in text.split(|c: char| c.is_whitespace() || c == ',') {
+        if !tag.is_empty() && !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            tags.push(tag.to_string());
+        }
+    }
+
+    tags
+}
+
+impl Card {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct TagFilter {
+    /// Tags a card needs, all of them or any one depending on `match_all`. When empty every card
+    /// is included.
+    pub include: Vec<String>,
+    /// Tags which leave a card out.
+    pub exclude: Vec<String>,
+    pub match_all: bool,
+}
+
+impl TagFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn matches(&self, card: &Card) -> bool {
+        let included = if self.include.is_empty() {
+            true
+        } else if self.match_all {
+            self.include.iter().all(|tag| card.has_tag(tag))
+        } else {
+            self.include.iter().any(|tag| card.has_tag(tag))
+        };
+
+        included && !self.exclude.iter().any(|tag| card.has_tag(tag))
+    }
+}
+
+impl Display for TagFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = if self.match_all { AND } else { OR };
+        let mut parts = vec![self.include.join(&format!(" {operator} "))];
+        parts.extend(self.exclude.iter().map(|tag| format!("{NOT} {tag}")));
+
+        write!(f, "{}", parts.join(" ").trim())
+    }
+}
+
+impl FromStr for TagFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = TagFilter::default();
+        let mut operator = None;
+
+        let mut words = s.split_whitespace();
+        while let Some(word) = words.next() {
+            if word == AND || word == OR {
+                if operator.is_some_and(|operator| operator != word) {
+                    return Err(format!("Tag filter '{s}' can't mix {AND} and {OR}."));
+                }
+                operator = Some(word);
+            } else if word == NOT {
+                let tag = words
+                    .next()
+                    .ok_or_else(|| format!("Expected a tag after {NOT} in '{s}'."))?;
+                filter.exclude.push(tag.to_string());
+            } else if let Some(tag) = word.strip_prefix('-').filter(|tag| !tag.is_empty()) {
+                filter.exclude.push(tag.to_string());
+            } else {
+                filter.include.push(word.to_string());
+            }
+        }
+
+        filter.match_all = operator == Some(AND);
+        Ok(filter)
+    }
+}
+
+impl Deck {
+    /// Every tag used in the deck, sorted.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .cards
+            .values()
+            .flat_map(|card| card.tags.iter().cloned())
+            .collect();
+        tags.sort_by_key(|tag| tag.to_lowercase());
+        tags.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+
+        tags
+    }
+
+    /// Ids of the items of the cards which pass the filter.
+    pub fn filtered_item_ids(&self, filter: &TagFilter) -> Vec<usize> {
+        self.item_ids()
+            .into_iter()
+            .filter(|id| self.item(*id).is_some_and(|item| filter.matches(item.card)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagged(tags: &[&str]) -> Card {
+        Card {
+            card_id: 1,
+            front: String::new(),
+            back: String::new(),
+            direction: None,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    fn filter(text: &str) -> TagFilter {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn and_needs_every_tag() {
+        let verbs = filter("verbs AND irregular");
+
+        assert!(verbs.match_all);
+        assert!(verbs.matches(&tagged(&["Irregular", "verbs"])));
+        assert!(!verbs.matches(&tagged(&["verbs"])));
+    }
+
+    #[test]
+    fn or_needs_any_tag() {
+        let chapters = filter("chapter-3 OR chapter-4");
+
+        assert!(!chapters.match_all);
+        assert!(chapters.matches(&tagged(&["chapter-4"])));
+        assert!(!chapters.matches(&tagged(&["chapter-5"])));
+    }
+
+    #[test]
+    fn not_and_minus_leave_cards_out() {
+        let chapters = filter("chapter-3 OR chapter-4 NOT draft -old");
+
+        assert_eq!(chapters.include, vec!["chapter-3", "chapter-4"]);
+        assert_eq!(chapters.exclude, vec!["draft", "old"]);
+        assert!(!chapters.matches(&tagged(&["chapter-3", "Draft"])));
+        assert!(filter("-draft").matches(&tagged(&[])));
+        // A lone dash is a tag rather than an empty exclusion.
+        assert_eq!(filter("-").include, vec!["-"]);
+    }
+
+    #[test]
+    fn filters_are_written_as_they_are_read() {
+        for text in ["verbs AND irregular NOT draft", "a OR b", "NOT draft", ""] {
+            assert_eq!(filter(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn mixed_operators_and_a_missing_tag_are_errors() {
+        assert!("a AND b OR c".parse::<TagFilter>().is_err());
+        assert!("a NOT".parse::<TagFilter>().is_err());
+    }
+
+    #[test]
+    fn repeated_tags_are_read_once() {
+        assert_eq!(
+            parse_tags("verbs, Verbs irregular,,"),
+            vec!["verbs", "irregular"]
+        );
+    }
+}
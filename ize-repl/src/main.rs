@@ -1,38 +1,262 @@
-use std::{env, error::Error};
+use std::{env, error::Error, io::{self, IsTerminal}, time::Duration};
 
-use ize_core::{prelude::*, Deck};
+use ize_core::{anki, prelude::*, Deck};
 use rustyline::{history::FileHistory, Editor};
 
+#[derive(Clone, Copy)]
+enum AnswerMode {
+    Flip,
+    /// Type the answer and have it checked with these options.
+    Typed(AnswerOptions),
+    MultipleChoice,
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
+    let (seed, args) = take_seed_arg(env::args().skip(1).collect())?;
 
     let mut rl = rustyline::DefaultEditor::new()?;
 
-    if args.len() > 1 {
-        let run_path = &args[1];
+    if let Some(run_path) = args.first() {
 
-        let (run, deck) = load_practice_run(run_path)?;
-        practice_run(&mut rl, run, deck)?;
+        if let Some((mut run, deck)) = load_run(&mut rl, run_path)? {
+            // The run shuffles from the given seed from now on.
+            if let Some(seed) = seed {
+                run.set_seed(seed);
+            }
+            let answers = answer_choice(&mut rl)?;
+            let limits = limits_choice(&mut rl)?;
+            practice_run(&mut rl, run, deck, answers, limits)?;
+        }
     }
 
     loop {
-        println!("Load deck? yes, no");
+        println!("Load deck? yes, no, stamp (write ids into a deck)");
         let line = rl.readline(">>")?;
 
         if line == "no" || line == "n" || line == "quit" || line == "q" {
             return Ok(())
         }
 
-        println!("Enter path:");
+        if line == "stamp" || line == "s" {
+            stamp_deck(&mut rl)?;
+            continue;
+        }
+
+        println!("Enter path, or several separated by ';' to study them together:");
+        let line = rl.readline(">>")?;
+
+        let mut deck_paths : Vec<String> = vec![];
+        for path in line.split(';').map(|path| path.trim()).filter(|path| !path.is_empty()) {
+            if !deck_paths.iter().any(|p| p == path) {
+                deck_paths.push(path.to_string());
+            }
+        }
+
+        let deck = match load_decks(&deck_paths) {
+            Ok(deck) => deck,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        let algorithm = algorithm_choice(&mut rl)?;
+        let layout = layout_choice(&mut rl)?;
+        let filter = filter_choice(&mut rl, &deck)?;
+        let learning_steps = learning_steps_choice(&mut rl)?;
+        let order = order_choice(&mut rl)?;
+        let mut run = PracticeRun::new_from_deck_with_filter(&deck, filter, algorithm.scheduler(), layout.boxes());
+        run.learning_steps = learning_steps;
+
+        run.set_deck_paths(&deck_paths);
+
+        let anki_paths : Vec<&String> = deck_paths.iter().filter(|path| anki::is_anki_package(path)).collect();
+        if !anki_paths.is_empty() && anki_state_choice(&mut rl)? {
+            for path in anki_paths {
+                if let Err(e) = anki::import_review_state(path, &mut run) {
+                    println!("{}", e);
+                }
+            }
+        }
+
+        // Fitting is best effort, an unreadable history shouldn't stop the run.
+        let records = run.review_history();
+        run.fit_scheduler(&records);
+
+        // Ordering may depend on the imported progress, so it comes last.
+        run.order = order;
+        if let Some(seed) = seed {
+            run.set_seed(seed);
+        }
+        run.shuffle(RunCategory::Remaining);
+        run.clear_journal();
+        println!("Seed: {} (--seed {} shuffles the same way)", run.seed(), run.seed());
+
+        let answers = answer_choice(&mut rl)?;
+        let limits = limits_choice(&mut rl)?;
+        practice_run(&mut rl, run, deck, answers, limits)?;
+
+    }
+
+}
+
+/// Load the run at `run_path`, asking for the deck's new location if it has moved.
+fn load_run(rl : &mut Editor<(), FileHistory>, run_path : &str) -> Result<Option<(PracticeRun, Deck)>, Box<dyn Error>> {
+    let mut result = load_practice_run(run_path);
+
+    loop {
+        match result {
+            Ok(loaded) => return Ok(Some(loaded)),
+            Err(IzeError::MissingDeck { deck_path, .. }) => {
+                println!("Deck '{}' not found. Enter its new path, or leave empty to skip:", deck_path.display());
+                let line = rl.readline(">>")?;
+                if line.is_empty() {
+                    return Ok(None);
+                }
+
+                result = load_practice_run_with_deck(run_path, &line);
+            }
+            Err(e) => {
+                println!("{}", e);
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Ask the user to pick one of `choices`, where an empty line picks the first.
+fn option_choice<T : Copy>(rl : &mut Editor<(), FileHistory>, prompt : &str, choices : &[(String, T)]) -> Result<T, Box<dyn Error>> {
+    loop {
+        let options : Vec<String> = choices.iter().enumerate()
+            .map(|(i, (name, _))| format!("{}: {}", i + 1, name))
+            .collect();
+        println!("{} {} (default 1)", prompt, options.join(", "));
+
+        let line = rl.readline(">>")?;
+        if line.is_empty() {
+            return Ok(choices[0].1);
+        }
+
+        match line.parse::<usize>() {
+            Ok(num) if (1..=choices.len()).contains(&num) => return Ok(choices[num - 1].1),
+            _ => println!("Unknown command '{}'", line),
+        }
+    }
+}
+
+fn algorithm_choice(rl : &mut Editor<(), FileHistory>) -> Result<Algorithm, Box<dyn Error>> {
+    let choices : Vec<(String, Algorithm)> = Algorithm::ALL.iter()
+        .map(|algorithm| (algorithm.name().to_string(), *algorithm))
+        .collect();
+
+    option_choice(rl, "Scheduler?", &choices)
+}
+
+fn layout_choice(rl : &mut Editor<(), FileHistory>) -> Result<BoxLayout, Box<dyn Error>> {
+    let choices : Vec<(String, BoxLayout)> = BoxLayout::ALL.iter()
+        .map(|layout| (layout.name(), *layout))
+        .collect();
+
+    option_choice(rl, "Boxes?", &choices)
+}
+
+/// Ask which tags to study, if the deck has any.
+fn filter_choice(rl : &mut Editor<(), FileHistory>, deck : &Deck) -> Result<TagFilter, Box<dyn Error>> {
+    let tags = deck.tags();
+    if tags.is_empty() {
+        return Ok(TagFilter::default());
+    }
+
+    loop {
+        println!("Tags: {}", tags.join(", "));
+        println!("Filter by tags, e.g. verbs AND irregular NOT draft (empty for all cards):");
+
+        let line = rl.readline(">>")?;
+        match line.parse::<TagFilter>() {
+            Ok(filter) if deck.filtered_item_ids(&filter).is_empty() => println!("No cards match '{}'", line),
+            Ok(filter) => return Ok(filter),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+fn order_choice(rl : &mut Editor<(), FileHistory>) -> Result<CardOrder, Box<dyn Error>> {
+    let choices : Vec<(String, CardOrder)> = CardOrder::ALL.iter()
+        .map(|order| (order.description().to_lowercase(), *order))
+        .collect();
+
+    option_choice(rl, "Order?", &choices)
+}
+
+fn anki_state_choice(rl : &mut Editor<(), FileHistory>) -> Result<bool, Box<dyn Error>> {
+    let choices = [("yes".to_string(), true), ("no".to_string(), false)];
+
+    option_choice(rl, "Import Anki review progress?", &choices)
+}
+
+fn answer_choice(rl : &mut Editor<(), FileHistory>) -> Result<AnswerMode, Box<dyn Error>> {
+    let choices = [
+        ("flip card".to_string(), AnswerMode::Flip),
+        ("type answer".to_string(), AnswerMode::Typed(AnswerOptions::default())),
+        ("type answer exactly".to_string(), AnswerMode::Typed(AnswerOptions::EXACT)),
+        ("multiple choice".to_string(), AnswerMode::MultipleChoice),
+    ];
+
+    option_choice(rl, "Answers?", &choices)
+}
+
+/// Write ids into a deck file for the cards which don't have one yet.
+fn stamp_deck(rl : &mut Editor<(), FileHistory>) -> Result<(), Box<dyn Error>> {
+    println!("Enter deck path:");
+    let deck_path = rl.readline(">>")?;
+
+    let mut choices = vec![("deck's own".to_string(), None)];
+    choices.extend(IdScheme::ALL.into_iter().map(|scheme| (scheme.name().to_string(), Some(scheme))));
+    let scheme = option_choice(rl, "Ids for new cards?", &choices)?;
+
+    match stamp_ids(deck_path.trim(), scheme) {
+        Ok(deck) => println!("All {} cards in {} have ids.", deck.cards.len(), deck_path.trim()),
+        Err(e) => println!("{}", e),
+    }
+
+    Ok(())
+}
+
+fn learning_steps_choice(rl : &mut Editor<(), FileHistory>) -> Result<Vec<usize>, Box<dyn Error>> {
+    loop {
+        println!("Bring back wrong answers after how many cards? (default {}, or none)", format_learning_steps(&DEFAULT_LEARNING_STEPS));
         let line = rl.readline(">>")?;
+        if line.trim().is_empty() {
+            return Ok(DEFAULT_LEARNING_STEPS.to_vec());
+        }
 
-        let deck = load_deck(&line)?;
-        let run = PracticeRun::new_from_deck(&deck);
+        match parse_learning_steps(&line) {
+            Ok(steps) => return Ok(steps),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
 
-        practice_run(&mut rl, run, deck)?;
+/// Ask for a whole number, where an empty line means none.
+fn number_choice(rl : &mut Editor<(), FileHistory>, prompt : &str) -> Result<Option<u64>, Box<dyn Error>> {
+    loop {
+        println!("{} (empty for none)", prompt);
+        let line = rl.readline(">>")?;
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
 
+        match line.trim().parse() {
+            Ok(number) => return Ok(Some(number)),
+            Err(_) => println!("'{}' is not a whole number", line),
+        }
     }
+}
+
+fn limits_choice(rl : &mut Editor<(), FileHistory>) -> Result<SessionLimits, Box<dyn Error>> {
+    let time_limit = number_choice(rl, "Time limit in minutes?")?.map(|minutes| Duration::from_secs(minutes * 60));
+    let max_cards = number_choice(rl, "Max cards?")?.map(|count| count as usize);
 
+    Ok(SessionLimits { time_limit, max_cards })
 }
 
 fn setup_run(category : RunCategory, run : &mut PracticeRun) {
@@ -41,25 +265,37 @@ fn setup_run(category : RunCategory, run : &mut PracticeRun) {
     run.shuffle(RunCategory::Remaining);
 }
 
-fn setup_run_all(run : &mut PracticeRun) {
-    run.move_category(RunCategory::Incorrect, RunCategory::Remaining);
-    run.move_category(RunCategory::Working, RunCategory::Remaining);
-    run.move_category(RunCategory::Memorized, RunCategory::Remaining);
-
-    run.shuffle(RunCategory::Remaining);
+/// Describe each box as "<number>: <name> (<count>)", numbering from `first`.
+fn box_choices(run : &PracticeRun, first : usize) -> Vec<String> {
+    run.box_categories().enumerate()
+        .map(|(i, category)| format!("{}: {} ({})",
+            i + first,
+            run.category_name(category).to_lowercase(),
+            run.category(category).map_or(0, |ids| ids.len())))
+        .collect()
 }
 
 fn category_choice(rl : &mut Editor<(), FileHistory>, run : &mut PracticeRun) -> Result<bool, Box<dyn Error>> 
 {
     loop {
-        let incorrect_len = run.incorrect.len();
-        let working_len = run.working.len();
-        let memorized_len = run.memorized.len();
-
-        let all = run.remaining.len() + incorrect_len + working_len + memorized_len;
+        let boxes : Vec<RunCategory> = run.box_categories().collect();
+        let all : usize = boxes.iter().filter_map(|category| run.category(*category)).map(|ids| ids.len()).sum();
+        let due_count = run.due_count(today());
+        let session_count = run.next_session_count();
+        // Only offered when there is something due, as an empty pile has no next card.
+        let due_choice = (due_count > 0).then_some(boxes.len() + 2);
+        let session_choice = (session_count > 0).then_some(boxes.len() + 2 + usize::from(due_choice.is_some()));
+
+        let mut choices = vec![format!("1: all ({})", all + run.remaining.len())];
+        choices.extend(box_choices(run, 2));
+        if let Some(due_choice) = due_choice {
+            choices.push(format!("{}: due today ({})", due_choice, due_count));
+        }
+        if let Some(session_choice) = session_choice {
+            choices.push(format!("{}: next session ({})", session_choice, session_count));
+        }
     
-        println!("1: all ({}), 2: incorrect ({}), 3: working ({}), 4: memorized ({}), or quit", 
-            all, incorrect_len, working_len, memorized_len);
+        println!("{}, o to change the order ({}), u to undo, or quit", choices.join(", "), run.order.description().to_lowercase());
     
         let line = rl.readline(">>")?;
 
@@ -67,18 +303,35 @@ fn category_choice(rl : &mut Editor<(), FileHistory>, run : &mut PracticeRun) ->
             return Ok(false);
         }
 
+        if line == "o" || line == "order" {
+            run.order = order_choice(rl)?;
+            continue;
+        }
+
+        if undo_command(run, &line) {
+            if run.remaining.is_empty() {
+                continue;
+            }
+            return Ok(true);
+        }
+
         if let Ok(num) = line.parse::<usize>() {
             match num {
-                1 => setup_run_all(run),
-                2 => setup_run(RunCategory::Incorrect, run),
-                3 => setup_run(RunCategory::Remaining, run),
-                4 => setup_run(RunCategory::Memorized, run),
+                1 => run.reset(),
+                n if n >= 2 && n < boxes.len() + 2 => setup_run(boxes[n - 2], run),
+                n if Some(n) == due_choice => run.queue_due(today()),
+                n if Some(n) == session_choice => run.start_session(),
                 _ =>  {
                     println!("Unknown command '{}'", line);
                     continue;
                 },
             }
 
+            if run.remaining.is_empty() {
+                println!("No cards to study.");
+                continue;
+            }
+
             return Ok(true);
         } else {
             println!("Unknown command '{}'", line);
@@ -88,40 +341,178 @@ fn category_choice(rl : &mut Editor<(), FileHistory>, run : &mut PracticeRun) ->
 
 }
 
-fn print_card(rl : &mut Editor<(), FileHistory>, card : &Card, show_back : bool) -> Result<(), Box<dyn Error>> {
+// Cloze blanks are shown in bold and answers bold and underlined, or in brackets when the
+// output isn't a terminal.
+fn print_spans(spans : &[Span]) {
+    let highlight = io::stdout().is_terminal();
+
+    for span in spans {
+        match span {
+            Span::Text(text) => print!("{}", text),
+            Span::Blank(text) if highlight => print!("\x1b[1m{}\x1b[0m", text),
+            Span::Answer(text) if highlight => print!("\x1b[1;4m{}\x1b[0m", text),
+            Span::Blank(text) => print!("{}", text),
+            Span::Answer(text) => print!("[{}]", text),
+        }
+    }
+
+    println!();
+}
+
+fn print_card(rl : &mut Editor<(), FileHistory>, item : &Item, show_back : bool) -> Result<(), Box<dyn Error>> {
     rl.clear_screen()?;
-    println!("{}", card.front);
+
+    // The back of a cloze already holds the whole text.
+    if show_back && matches!(item.kind, ItemKind::Cloze(_)) {
+        print_spans(&item.back());
+        return Ok(());
+    }
+
+    print_spans(&item.front());
 
     if show_back {
-        println!("{}", card.back);
+        print_spans(&item.back());
     }
 
     Ok(())
 }
 
-fn next_card(rl : &mut Editor<(), FileHistory>, run : &mut PracticeRun, deck : &Deck) -> Result<bool, Box<dyn Error>> {
-    let card_id = run.remaining.last().expect("Next card called on empty deck.");
+// Missing characters in green and extra ones struck through in red, or marked as {+missing+} and
+// [-extra-] when the output isn't a terminal.
+fn print_diff(diff : &[Diff]) {
+    let highlight = io::stdout().is_terminal();
+
+    for part in diff {
+        match part {
+            Diff::Same(text) => print!("{}", text),
+            Diff::Missing(text) if highlight => print!("\x1b[1;32m{}\x1b[0m", text),
+            Diff::Extra(text) if highlight => print!("\x1b[9;31m{}\x1b[0m", text),
+            Diff::Missing(text) => print!("{{+{}+}}", text),
+            Diff::Extra(text) => print!("[-{}-]", text),
+        }
+    }
+
+    println!();
+}
+
+/// Ask for the number of one of the choices, or None to quit.
+fn multiple_choice(rl : &mut Editor<(), FileHistory>, question : &MultipleChoice) -> Result<Option<usize>, Box<dyn Error>> {
+    for (i, choice) in question.choices.iter().enumerate() {
+        println!("{}: {}", i + 1, choice.replace('\n', " "));
+    }
 
-    let card = deck.cards.get(card_id).expect("Card not found in deck.");
+    loop {
+        let line = rl.readline("choice>")?;
 
-    print_card(rl, card, false)?;
+        if line == "q" || line == "quit" {
+            return Ok(None);
+        }
+
+        match line.parse::<usize>() {
+            Ok(num) if (1..=question.choices.len()).contains(&num) => return Ok(Some(num - 1)),
+            _ => println!("Unknown command '{}'", line),
+        }
+    }
+}
+
+fn next_card(rl : &mut Editor<(), FileHistory>, run : &mut PracticeRun, deck : &Deck, answers : AnswerMode, session : &mut PracticeSession) -> Result<bool, Box<dyn Error>> {
+    let card_id = *run.remaining.last().expect("Next card called on empty deck.");
+
+    let card = deck.item(card_id).expect("Card not found in deck.");
+
+    let mut timer = ReviewTimer::start();
+    print_card(rl, &card, false)?;
+
+    if let AnswerMode::MultipleChoice = answers {
+        if let Some(question) = run.multiple_choice(deck, card_id, DISTRACTOR_COUNT) {
+            let Some(choice) = multiple_choice(rl, &question)? else {
+                return Ok(false);
+            };
+
+            timer.flip();
+            print_card(rl, &card, true)?;
+
+            let correct = question.is_correct(choice);
+            let destination = run.suggested_category(card_id, correct);
+            println!("{}, moving to {}", if correct { "Correct" } else { "Incorrect" }, run.category_name(destination).to_lowercase());
+            file_card(run, card_id, destination, &timer, session)?;
+
+            let line = rl.readline("< enter >")?;
+            undo_command(run, &line);
+            return Ok(line != "q" && line != "quit");
+        }
+    }
 
-    let line = rl.readline("< enter >")?;
+    let line = match answers {
+        AnswerMode::Typed(_) => rl.readline("answer>")?,
+        _ => rl.readline("< enter >")?,
+    };
 
     if line == "q" || line == "quit" {
         return Ok(false);
     }
 
-    print_card(rl, card,  true)?;
+    // A typed answer could be "u", so only flipped cards take commands here.
+    if let AnswerMode::Flip = answers {
+        if undo_command(run, &line) {
+            return Ok(true);
+        }
+    }
 
-    card_choice(rl, *card_id, run)
+    timer.flip();
+    print_card(rl, &card,  true)?;
+
+    let suggestion = match answers {
+        AnswerMode::Typed(options) => {
+            let check = check_answer(&line, &card.answer(), &options);
+            print_diff(&check.diff);
+            println!("{}", if check.correct { "Correct" } else { "Incorrect" });
+
+            Some(run.suggested_category(card_id, check.correct))
+        }
+        _ => None,
+    };
+
+    card_choice(rl, card_id, run, &timer, suggestion, session)
 }
 
-fn card_choice(rl : &mut Editor<(), FileHistory>, card_id : usize, run : &mut PracticeRun) -> Result<bool, Box<dyn Error>> {
+/// Handle `u`/`undo` and `redo`, returning false for any other line.
+fn undo_command(run : &mut PracticeRun, line : &str) -> bool {
+    let (done, name) = match line {
+        "u" | "undo" => (run.undo(), "undo"),
+        "redo" => (run.redo(), "redo"),
+        _ => return false,
+    };
+
+    if !done {
+        println!("Nothing to {}", name);
+    }
+
+    true
+}
+
+fn log_review(run : &mut PracticeRun, record : Option<ReviewRecord>) {
+    if let Some(record) = record {
+        if let Err(e) = run.log_review(record) {
+            println!("Failed to record review: {}", e);
+        }
+    }
+}
+
+/// Ask where the card goes, where an empty line accepts the `suggestion` of a checked answer.
+fn card_choice(rl : &mut Editor<(), FileHistory>, card_id : usize, run : &mut PracticeRun, timer : &ReviewTimer, suggestion : Option<RunCategory>, session : &mut PracticeSession) -> Result<bool, Box<dyn Error>> {
 
     loop {
-        println!("1: skip ({}), 2: incorrect ({}), 3: working ({}), 4: memorized ({}), or quit?",
-            run.remaining.len(), run.incorrect.len(), run.working.len(), run.memorized.len());
+        let mut choices = vec![match run.relearning_count() {
+            0 => format!("1: skip ({})", run.remaining.len()),
+            relearning => format!("1: skip ({}, {} to relearn)", run.remaining.len(), relearning),
+        }];
+        choices.extend(box_choices(run, 2));
+        println!("{}, u to undo, or quit?", choices.join(", "));
+
+        if let Some(category) = suggestion {
+            println!("Suggested: {} (enter to accept)", run.category_name(category).to_lowercase());
+        }
 
         let line = rl.readline(">>")?;
 
@@ -129,23 +520,45 @@ fn card_choice(rl : &mut Editor<(), FileHistory>, card_id : usize, run : &mut Pr
             return Ok(false);
         }
 
-        if let Ok(choice) = line.parse::<usize>() {
-            match choice {
-                1 => run.skip(),
-                2 => run.move_index(card_id, RunCategory::Remaining, RunCategory::Incorrect)?,
-                3 => run.move_index(card_id, RunCategory::Remaining, RunCategory::Working)?,
-                4 => run.move_index(card_id, RunCategory::Remaining, RunCategory::Memorized)?,
-                _ => {
-                    println!("Unknown command {}", line);
-                    continue;
-                } 
+        if undo_command(run, &line) {
+            return Ok(true);
+        }
+
+        let destination = match (line.parse::<usize>(), suggestion) {
+            (_, Some(category)) if line.is_empty() => Some(category),
+            (Ok(1), _) => Some(RunCategory::Remaining),
+            (Ok(n), _) if n >= 2 && n < run.boxes.len() + 2 => Some(RunCategory::Box(n - 2)),
+            (Ok(_), _) => {
+                println!("Unknown command {}", line);
+                continue;
             }
+            (Err(_), _) => None,
+        };
+
+        if let Some(destination) = destination {
+            file_card(run, card_id, destination, timer, session)?;
         }
 
         return Ok(true);
     }
 }
 
+fn file_card(run : &mut PracticeRun, card_id : usize, destination : RunCategory, timer : &ReviewTimer, session : &mut PracticeSession) -> Result<(), Box<dyn Error>> {
+    if let RunCategory::Remaining = destination {
+        run.skip();
+        return Ok(());
+    }
+
+    let record = run.review_record(card_id, destination, timer);
+    if let Some(record) = &record {
+        session.record(record);
+    }
+    run.move_index(card_id, RunCategory::Remaining, destination)?;
+    log_review(run, record);
+
+    Ok(())
+}
+
 fn save_run_prompt(rl: &mut Editor<(), FileHistory>, run : &mut PracticeRun) -> Result<(), Box<dyn Error>> {
     println!("Would you like to save paractice run? yes, no");
     let line = rl.readline(">>")?;
@@ -153,17 +566,34 @@ fn save_run_prompt(rl: &mut Editor<(), FileHistory>, run : &mut PracticeRun) ->
         return Ok(())
     }
 
-    let path = rl.readline_with_initial(">>", (&run.last_save,""))?;
+    loop {
+        let path = rl.readline_with_initial(">>", (&run.last_save,""))?;
+
+        if path.is_empty() {
+            return Ok(());
+        }
 
-    if path.is_empty() {
-        return Ok(());
+        // The previous file is left intact when saving fails, so let the user pick another path.
+        match save_practice_run(&path, run) {
+            Ok(()) => return Ok(()),
+            Err(e) => println!("{}\nEnter another path, or leave empty to discard:", e),
+        }
     }
-
-    save_practice_run(&path, run)
 }
 
-fn practice_run(rl : &mut Editor<(), FileHistory>, mut run: PracticeRun, deck: Deck) -> Result<(), Box<dyn Error>> {
+fn practice_run(rl : &mut Editor<(), FileHistory>, mut run: PracticeRun, deck: Deck, answers : AnswerMode, limits : SessionLimits) -> Result<(), Box<dyn Error>> {
+    let mut session = PracticeSession::start(limits);
+
     loop {
+        if session.is_over() {
+            println!("Session over after {} cards and {}. Keep going? yes, no", session.reviewed(), format_duration(session.elapsed()));
+            let line = rl.readline(">>")?;
+            if line != "yes" && line != "y" {
+                break;
+            }
+
+            session.limits = SessionLimits::default();
+        }
 
         if run.remaining.is_empty() {
             println!("No cards remaining would you like to start over?");
@@ -175,11 +605,12 @@ fn practice_run(rl : &mut Editor<(), FileHistory>, mut run: PracticeRun, deck: D
             }
         }
 
-        if !next_card( rl, &mut run, &deck)? {
+        if !next_card( rl, &mut run, &deck, answers, &mut session)? {
             break
         }
     }
 
+    println!("{}", session.summary());
     save_run_prompt(rl, &mut run)?;
     Ok(())
 }
@@ -0,0 +1,360 @@
+use cursive::{
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, LinearLayout, SelectView, TextArea, TextView},
+    Cursive,
+};
+use ize_core::{prelude::*, Deck};
+
+use crate::{file_explorer::show_file_explorer, utils::show_error};
+
+const DECK_EDITOR: &str = "DeckEditor";
+const DECK_EDITOR_SEARCH: &str = "DeckEditorSearch";
+const DECK_EDITOR_CARDS: &str = "DeckEditorCards";
+const CARD_FORM_FRONT: &str = "CardFormFront";
+const CARD_FORM_BACK: &str = "CardFormBack";
+const CARD_FORM_DIRECTION: &str = "CardFormDirection";
+const CARD_FORM_TAGS: &str = "CardFormTags";
+
+const SUMMARY_WIDTH: usize = 30;
+
+struct EditorState {
+    deck_path: String,
+    deck: Deck,
+    unsaved: bool,
+}
+
+/// The first line of `text`, shortened to fit in a column of the card list.
+fn summary(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() > SUMMARY_WIDTH {
+        let short: String = line.chars().take(SUMMARY_WIDTH - 3).collect();
+        format!("{short}...")
+    } else {
+        line.to_string()
+    }
+}
+
+fn matches_search(card: &Card, search: &str) -> bool {
+    let search = search.to_lowercase();
+    card.front.to_lowercase().contains(&search)
+        || card.back.to_lowercase().contains(&search)
+        || card.has_tag(&search)
+}
+
+fn editor_title(state: &EditorState) -> String {
+    let marker = if state.unsaved { " *" } else { "" };
+    format!("Edit deck - {}{}", state.deck_path, marker)
+}
+
+/// Refill the card list from the deck, keeping only cards which match the search.
+fn refresh_cards(siv: &mut Cursive) {
+    let search = siv
+        .call_on_name(DECK_EDITOR_SEARCH, |view: &mut EditView| view.get_content())
+        .expect("View not found");
+
+    let (items, title) = {
+        let state = siv
+            .user_data::<EditorState>()
+            .expect("Editor state not found");
+
+        let mut cards: Vec<&Card> = state
+            .deck
+            .cards
+            .values()
+            .filter(|card| matches_search(card, &search))
+            .collect();
+        cards.sort_by_key(|card| card.card_id);
+
+        let items: Vec<(String, usize)> = cards
+            .iter()
+            .map(|card| {
+                (
+                    format!(
+                        "{:>5}  {:<width$}  {}",
+                        card.card_id,
+                        summary(&card.front),
+                        summary(&card.back),
+                        width = SUMMARY_WIDTH
+                    ),
+                    card.card_id,
+                )
+            })
+            .collect();
+
+        (items, editor_title(state))
+    };
+
+    siv.call_on_name(DECK_EDITOR_CARDS, |view: &mut SelectView<usize>| {
+        let selected = view.selected_id();
+        view.clear();
+        view.add_all(items);
+        if let Some(selected) = selected {
+            view.set_selection(selected.min(view.len().saturating_sub(1)));
+        }
+    })
+    .expect("View not found");
+
+    siv.call_on_name(DECK_EDITOR, |view: &mut Dialog| view.set_title(title))
+        .expect("View not found");
+}
+
+fn selected_card(siv: &mut Cursive) -> Option<usize> {
+    siv.call_on_name(DECK_EDITOR_CARDS, |view: &mut SelectView<usize>| {
+        view.selection()
+    })
+    .expect("View not found")
+    .map(|id| *id)
+}
+
+fn read_text_area(siv: &mut Cursive, name: &str) -> String {
+    siv.call_on_name(name, |view: &mut TextArea| view.get_content().to_string())
+        .expect("View not found")
+}
+
+/// Show a form for the card `card_id`, or for a new card if `None`.
+fn show_card_form(siv: &mut Cursive, card_id: Option<usize>) {
+    let (front, back, direction, tags) = card_id
+        .and_then(|id| {
+            let state = siv.user_data::<EditorState>()?;
+            state.deck.cards.get(&id).map(|card| {
+                (
+                    card.front.clone(),
+                    card.back.clone(),
+                    card.direction,
+                    card.tags.join(" "),
+                )
+            })
+        })
+        .unwrap_or_default();
+
+    let mut directions = SelectView::new().popup();
+    directions.add_item("Deck default", None);
+    for direction in Direction::ALL {
+        directions.add_item(direction.name(), Some(direction));
+    }
+    let selected = Direction::ALL
+        .iter()
+        .position(|d| Some(*d) == direction)
+        .map_or(0, |index| index + 1);
+    directions.set_selection(selected);
+
+    let title = match card_id {
+        Some(id) => format!("Card {id}"),
+        None => "New card".to_string(),
+    };
+
+    let layout = LinearLayout::vertical()
+        .child(TextView::new("Front"))
+        .child(
+            TextArea::new()
+                .content(front)
+                .with_name(CARD_FORM_FRONT)
+                .min_height(4),
+        )
+        .child(TextView::new("Back"))
+        .child(
+            TextArea::new()
+                .content(back)
+                .with_name(CARD_FORM_BACK)
+                .min_height(4),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Direction: "))
+                .child(directions.with_name(CARD_FORM_DIRECTION)),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new("Tags: "))
+                .child(
+                    EditView::new()
+                        .content(tags)
+                        .with_name(CARD_FORM_TAGS)
+                        .full_width(),
+                ),
+        );
+
+    siv.add_layer(
+        Dialog::around(layout.fixed_width(60))
+            .title(title)
+            .button("Cancel", |s| {
+                s.pop_layer();
+            })
+            .button("Ok", move |s| {
+                let front = read_text_area(s, CARD_FORM_FRONT);
+                let back = read_text_area(s, CARD_FORM_BACK);
+                let direction = s
+                    .call_on_name(
+                        CARD_FORM_DIRECTION,
+                        |view: &mut SelectView<Option<Direction>>| view.selection(),
+                    )
+                    .expect("View not found")
+                    .and_then(|direction| *direction);
+                let tags = s
+                    .call_on_name(CARD_FORM_TAGS, |view: &mut EditView| {
+                        parse_tags(&view.get_content())
+                    })
+                    .expect("View not found");
+
+                if front.trim().is_empty() {
+                    s.add_layer(Dialog::info("A card needs a front."));
+                    return;
+                }
+
+                s.with_user_data(|state: &mut EditorState| {
+                    let card_id = card_id.unwrap_or_else(|| state.deck.new_card_id(&front));
+                    state.deck.cards.insert(
+                        card_id,
+                        Card {
+                            card_id,
+                            front,
+                            back,
+                            direction,
+                            tags,
+                        },
+                    );
+                    state.unsaved = true;
+                });
+
+                s.pop_layer();
+                refresh_cards(s);
+            }),
+    );
+}
+
+fn delete_card(siv: &mut Cursive) {
+    let Some(card_id) = selected_card(siv) else {
+        return;
+    };
+
+    siv.add_layer(
+        Dialog::text(format!("Delete card {card_id}?"))
+            .title("Delete card")
+            .button("Cancel", |s| {
+                s.pop_layer();
+            })
+            .button("Delete", move |s| {
+                s.with_user_data(|state: &mut EditorState| {
+                    state.deck.cards.remove(&card_id);
+                    state.unsaved = true;
+                });
+
+                s.pop_layer();
+                refresh_cards(s);
+            }),
+    );
+}
+
+fn save(siv: &mut Cursive) -> bool {
+    let result = {
+        let state = siv
+            .user_data::<EditorState>()
+            .expect("Editor state not found");
+        save_deck(&state.deck_path, &state.deck)
+    };
+
+    match result {
+        Ok(()) => {
+            siv.with_user_data(|state: &mut EditorState| state.unsaved = false);
+            refresh_cards(siv);
+            true
+        }
+        Err(e) => {
+            show_error(siv, &e);
+            false
+        }
+    }
+}
+
+fn close_editor(siv: &mut Cursive) {
+    let unsaved = siv
+        .user_data::<EditorState>()
+        .is_some_and(|state| state.unsaved);
+
+    if !unsaved {
+        siv.pop_layer();
+        return;
+    }
+
+    siv.add_layer(
+        Dialog::text("Save changes to the deck?")
+            .title("Unsaved changes")
+            .button("Cancel", |s| {
+                s.pop_layer();
+            })
+            .button("Discard", |s| {
+                s.pop_layer();
+                s.pop_layer();
+            })
+            .button("Save", |s| {
+                s.pop_layer();
+                if save(s) {
+                    s.pop_layer();
+                }
+            }),
+    );
+}
+
+pub fn show_deck_editor(siv: &mut Cursive, deck_path: &str, deck: Deck) {
+    siv.set_user_data(EditorState {
+        deck_path: deck_path.to_string(),
+        deck,
+        unsaved: false,
+    });
+
+    let cards = SelectView::<usize>::new()
+        .on_submit(|s, id: &usize| show_card_form(s, Some(*id)))
+        .with_name(DECK_EDITOR_CARDS)
+        .scrollable()
+        .fixed_size((75, 15));
+
+    let search = LinearLayout::horizontal()
+        .child(TextView::new("Search: "))
+        .child(
+            EditView::new()
+                .on_edit(|s, _, _| refresh_cards(s))
+                .with_name(DECK_EDITOR_SEARCH)
+                .full_width(),
+        );
+
+    let layout = LinearLayout::vertical().child(search).child(cards);
+
+    siv.add_layer(
+        Dialog::around(layout)
+            .button("Add", |s| show_card_form(s, None))
+            .button("Edit", |s| {
+                if let Some(card_id) = selected_card(s) {
+                    show_card_form(s, Some(card_id));
+                }
+            })
+            .button("Delete", delete_card)
+            .button("Save", |s| {
+                save(s);
+            })
+            .button("Close", close_editor)
+            .with_name(DECK_EDITOR),
+    );
+
+    refresh_cards(siv);
+}
+
+fn file_explorer_edit_deck(siv: &mut Cursive, file_name: &str) {
+    match load_deck(file_name) {
+        Err(e) => show_error(siv, &e),
+        Ok(deck) => {
+            siv.pop_layer();
+            show_deck_editor(siv, file_name, deck);
+        }
+    }
+}
+
+pub fn edit_deck(siv: &mut Cursive) {
+    show_file_explorer(
+        siv,
+        "./".to_string(),
+        Box::new(file_explorer_edit_deck),
+        Box::new(|s| {
+            s.pop_layer();
+        }),
+    );
+}
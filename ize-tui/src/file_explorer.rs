@@ -2,8 +2,9 @@ use std::{
     error::Error,
     fs,
     path::{Path, PathBuf},
+    rc::Rc,
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::Mutex,
 };
 
 use cursive::{
@@ -14,9 +15,12 @@ use cursive::{
 
 use crate::utils::show_error;
 
+pub type FileAction = Box<dyn Fn(&mut Cursive, &str)>;
+pub type CancelAction = Box<dyn Fn(&mut Cursive)>;
+
 struct FileState {
     current_directory: String,
-    file_action: Box<dyn Fn(&mut Cursive, &str)>,
+    file_action: FileAction,
 }
 
 const FILE_EXPLORER_SELECT_LIST: &str = "FILE_EXPLORER_SELECT_LIST";
@@ -72,8 +76,8 @@ fn on_file_selector_submit(s: &mut Cursive, file: &String, file_state: &Mutex<Fi
 pub fn show_file_explorer(
     siv: &mut Cursive,
     base_path: String,
-    select_action: Box<dyn Fn(&mut Cursive, &str)>,
-    cancel_action: Box<dyn Fn(&mut Cursive)>,
+    select_action: FileAction,
+    cancel_action: CancelAction,
 ) {
     let base_path = Path::new(&base_path)
         .canonicalize()
@@ -82,7 +86,7 @@ pub fn show_file_explorer(
         .unwrap()
         .to_string();
 
-    let fs = Arc::new(Mutex::new(FileState {
+    let fs = Rc::new(Mutex::new(FileState {
         current_directory: base_path.clone(),
         file_action: select_action,
     }));
@@ -130,16 +134,15 @@ fn read_selected_file(s: &mut Cursive) -> String {
     .expect("Expected view.")
 }
 
-fn get_file_full_path(current_dir : &str, file_name : &str) -> String
-{
-    let file_name_path = Path::new (file_name);
+fn get_file_full_path(current_dir: &str, file_name: &str) -> String {
+    let file_name_path = Path::new(file_name);
     let current_dir_path = Path::new(current_dir);
 
     if file_name_path.is_absolute() {
         file_name.to_string()
     } else {
         let mut abs_path = current_dir_path.canonicalize().unwrap();
-        abs_path.push(&file_name_path);
+        abs_path.push(file_name_path);
 
         abs_path.to_str().unwrap().to_string()
     }
@@ -166,7 +169,7 @@ fn update_directory_view(siv: &mut Cursive, fs: &Mutex<FileState>) {
         .unwrap();
 
     if let Err(err) = result {
-        show_error(siv, &err.as_ref());
+        show_error(siv, err.as_ref());
         return;
     }
 
@@ -181,10 +184,8 @@ fn read_choices(path: PathBuf) -> Result<Vec<String>, Box<dyn Error>> {
     let paths = fs::read_dir(path)?;
 
     let mut file_names = vec![".".to_string(), "..".to_string()];
-    for path in paths {
-        if let Ok(path) = path {
-            file_names.push(path.file_name().to_str().unwrap().to_string());
-        }
+    for path in paths.flatten() {
+        file_names.push(path.file_name().to_str().unwrap().to_string());
     }
 
     Ok(file_names)
@@ -1,26 +1,37 @@
-use std::{env, error::Error};
+use std::env;
 
+mod deck_editor;
 mod file_explorer;
+mod new_deck;
 mod practice_run;
+mod run_options;
 mod utils;
 
 use cursive::{
+    event::Event,
+    utils::markup::StyledString,
     view::Nameable,
-    views::{Button, Dialog, DummyView, LinearLayout},
+    views::{Button, Dialog, DummyView, LinearLayout, SelectView},
     Cursive,
 };
+use deck_editor::edit_deck;
 use file_explorer::show_file_explorer;
-use ize_core::{prelude::*, Deck, PracticeRun};
-use practice_run::begin_run;
-use utils::show_error;
+use ize_core::{anki, prelude::*, Deck, PracticeRun};
+use new_deck::new_deck;
+use practice_run::{begin_run, update_timer};
+use run_options::{show_run_options, AnswerMode, RunOptions, DEFAULT_SEED};
+use utils::{show_error, styled_spans};
 
 const MAIN_MENU: &str = "MainMenu";
-const RUN_SELECT: &str = "RunSelect";
 
 struct RunState {
     run_data: RunData,
     card_content_state: CardContentState,
     count: usize,
+    timer: ReviewTimer,
+    answer_mode: AnswerMode,
+    answer_options: AnswerOptions,
+    session: PracticeSession,
 }
 
 struct RunData {
@@ -29,14 +40,14 @@ struct RunData {
 }
 
 impl RunData {
-    pub fn current_card_front(&self) -> Option<String> {
+    pub fn current_card_front(&self) -> Option<StyledString> {
         let current_card_id = self.run.remaining.last()?;
-        Some(self.deck.cards[current_card_id].front.clone())
+        Some(styled_spans(&self.deck.item(*current_card_id)?.front()))
     }
 
-    pub fn current_card_back(&self) -> Option<String> {
-        let current_card_id = self.run.remaining.last().unwrap();
-        Some(self.deck.cards[current_card_id].back.clone())
+    pub fn current_card_back(&self) -> Option<StyledString> {
+        let current_card_id = self.run.remaining.last()?;
+        Some(styled_spans(&self.deck.item(*current_card_id)?.back()))
     }
 
     pub fn is_done(&self) -> bool {
@@ -50,32 +61,55 @@ enum CardContentState {
     Back,
 }
 
-fn try_load_args(siv : &mut Cursive, path : &str) -> Result<(), Box<dyn Error>> {
-    // First try loading it as a run.
-   let result = load_run_state(path);
-
-   // If that doesn't work load it as a deck.
-   let run_state = if let Ok(run_state) = result {
-    run_state
-   } else {
-    new_run_state(path).unwrap()
-   };
+fn try_load_args(siv: &mut Cursive, paths: &[String]) -> Result<(), IzeError> {
+    // Several paths are decks to study together, a single one may also be a run.
+    let run_state = if paths.len() > 1 {
+        new_run_state(paths, &RunOptions::default())?
+    } else {
+        // First try loading it as a run.
+        let result = load_run_state(&paths[0]);
+
+        // If that doesn't work load it as a deck, unless it is a run whose deck has moved.
+        match result {
+            Ok(run_state) => run_state,
+            Err(e @ IzeError::MissingDeck { .. }) => return Err(e),
+            Err(_) => new_run_state(paths, &RunOptions::default())?,
+        }
+    };
 
-   siv.pop_layer();
-   siv.set_user_data(run_state);
-   begin_run(siv);
+    siv.pop_layer();
+    siv.set_user_data(run_state);
+    begin_run(siv);
 
-   Ok(())
+    Ok(())
 }
 
 fn main() {
     let mut siv = cursive::default();
 
-    let args : Vec<String> = env::args().collect();
-    if args.len() >= 2 && try_load_args(&mut siv, &args[1]).is_ok() {
-    }
-    else {
+    // Redraw every second to keep the session timer going.
+    siv.set_fps(1);
+    siv.add_global_callback(Event::Refresh, update_timer);
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let paths = match take_seed_arg(args) {
+        Ok((seed, paths)) => {
+            if let Some(seed) = seed {
+                DEFAULT_SEED.get_or_init(|| seed);
+            }
+            paths
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    if paths.is_empty() {
+        main_menu(&mut siv);
+    } else if let Err(e) = try_load_args(&mut siv, &paths) {
         main_menu(&mut siv);
+        show_error(&mut siv, &e);
     }
 
     siv.run();
@@ -89,6 +123,7 @@ fn main_menu(siv: &mut Cursive) {
         .child(Button::new("Edit Deck", edit_deck))
         .child(Button::new("New Run", new_run))
         .child(Button::new("Resume Run", resume_run))
+        .child(Button::new("Stamp Ids", stamp_deck_ids))
         .child(DummyView)
         .child(Button::new("Quit", Cursive::quit));
 
@@ -99,16 +134,12 @@ fn main_menu(siv: &mut Cursive) {
     siv.add_layer(layer);
 }
 
-fn new_deck(siv: &mut Cursive) {}
-
-fn edit_deck(siv: &mut Cursive) {}
-
-fn file_explorer_load_deck_new_run(siv: &mut Cursive, file_name: &str) {
-    let run_state = new_run_state(file_name);
+fn file_explorer_load_deck_new_run(siv: &mut Cursive, deck_paths: &[String], options: &RunOptions) {
+    let run_state = new_run_state(deck_paths, options);
 
     match run_state {
         Err(e) => {
-            show_error(siv, e.as_ref());
+            show_error(siv, &e);
         }
         Ok(run_state) => {
             siv.pop_layer();
@@ -123,7 +154,7 @@ fn file_explorer_resume_run(siv: &mut Cursive, file_name: &str) {
 
     match run_state {
         Err(e) => {
-            show_error(siv, e.as_ref());
+            show_error(siv, &e);
         }
         Ok(run_state) => {
             siv.pop_layer();
@@ -137,7 +168,7 @@ fn new_run(siv: &mut Cursive) {
     show_file_explorer(
         siv,
         "./".to_string(),
-        Box::new(file_explorer_load_deck_new_run),
+        Box::new(show_run_options),
         Box::new(|s| {
             s.pop_layer();
         }),
@@ -155,27 +186,129 @@ fn resume_run(siv: &mut Cursive) {
     );
 }
 
-fn load_run_state(run_path: &str) -> Result<RunState, Box<dyn Error>> {
-    let (run, deck) = load_practice_run(run_path)?;
+/// Write ids into a deck file for the cards which don't have one yet.
+fn stamp_deck_ids(siv: &mut Cursive) {
+    show_file_explorer(
+        siv,
+        "./".to_string(),
+        Box::new(|s, deck_path| {
+            let deck_path = deck_path.to_string();
+            let mut schemes = SelectView::new().item("Deck's own", None);
+            for scheme in IdScheme::ALL {
+                schemes.add_item(scheme.name(), Some(scheme));
+            }
+            schemes.set_on_submit(move |s, scheme: &Option<IdScheme>| {
+                s.pop_layer();
+                match stamp_ids(&deck_path, *scheme) {
+                    Err(e) => show_error(s, &e),
+                    Ok(deck) => {
+                        s.pop_layer();
+                        s.add_layer(Dialog::info(format!(
+                            "All {} cards in {} have ids.",
+                            deck.cards.len(),
+                            deck_path
+                        )));
+                    }
+                }
+            });
+
+            s.add_layer(
+                Dialog::around(schemes)
+                    .title("Ids for new cards")
+                    .dismiss_button("Cancel"),
+            );
+        }),
+        Box::new(|s| {
+            s.pop_layer();
+        }),
+    );
+}
 
-    let run_data = RunData { deck, run };
-    Ok(RunState {
+/// Ask for the new location of the deck used by the run at `run_path` and resume the run with it.
+pub fn locate_deck(siv: &mut Cursive, run_path: &str) {
+    let run_path = run_path.to_string();
+    show_file_explorer(
+        siv,
+        "./".to_string(),
+        Box::new(move |s, deck_path| {
+            match load_practice_run_with_deck(&run_path, deck_path) {
+                Err(e) => show_error(s, &e),
+                Ok((run, deck)) => {
+                    // The run replaces the file explorers and menus below it.
+                    while s.pop_layer().is_some() {}
+                    s.set_user_data(resumed_run_state(run, deck));
+                    begin_run(s);
+                }
+            }
+        }),
+        Box::new(|s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+fn run_state(run: PracticeRun, deck: Deck) -> RunState {
+    RunState {
         card_content_state: CardContentState::Front,
-        run_data,
+        run_data: RunData { deck, run },
         count: 0,
-    })
+        timer: ReviewTimer::start(),
+        answer_mode: AnswerMode::Flip,
+        answer_options: AnswerOptions::default(),
+        session: PracticeSession::start(SessionLimits::default()),
+    }
 }
 
-fn new_run_state(deck_path: &str) -> Result<RunState, Box<dyn Error>> {
-    let deck = load_deck(deck_path)?;
+fn load_run_state(run_path: &str) -> Result<RunState, IzeError> {
+    let (run, deck) = load_practice_run(run_path)?;
 
-    let mut run = PracticeRun::new_from_deck(&deck);
-    run.deck_path = deck_path.to_string();
-    let run_data = RunData { deck, run };
+    Ok(resumed_run_state(run, deck))
+}
 
-    Ok(RunState {
-        card_content_state: CardContentState::Front,
-        run_data,
-        count: 0,
-    })
+/// A run loaded from a file shuffles from the seed given with `--seed` from now on, if there is
+/// one.
+fn resumed_run_state(mut run: PracticeRun, deck: Deck) -> RunState {
+    if let Some(seed) = DEFAULT_SEED.get() {
+        run.set_seed(*seed);
+    }
+
+    run_state(run, deck)
+}
+
+fn new_run_state(deck_paths: &[String], options: &RunOptions) -> Result<RunState, IzeError> {
+    let deck = load_decks(deck_paths)?;
+
+    let mut run = PracticeRun::new_from_deck_with_filter(
+        &deck,
+        options.filter.clone(),
+        options.algorithm.scheduler(),
+        options.layout.boxes(),
+    );
+    run.set_deck_paths(deck_paths);
+    run.learning_steps = options.learning_steps.clone();
+
+    if options.import_anki_state {
+        for deck_path in deck_paths.iter().filter(|path| anki::is_anki_package(path)) {
+            anki::import_review_state(deck_path, &mut run)?;
+        }
+    }
+
+    // Fitting is best effort, an unreadable history shouldn't stop the run.
+    let records = run.review_history();
+    run.fit_scheduler(&records);
+
+    // Ordering may depend on the imported progress, so it comes last.
+    run.order = options.order;
+    if let Some(seed) = options.seed {
+        run.set_seed(seed);
+    }
+    run.shuffle(RunCategory::Remaining);
+    run.clear_journal();
+
+    let mut state = run_state(run, deck);
+    state.answer_mode = options.answer_mode;
+    state.answer_options = options.answer_options;
+    state.session = PracticeSession::start(options.limits);
+
+    Ok(state)
 }
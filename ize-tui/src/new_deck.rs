@@ -0,0 +1,210 @@
+use std::path::Path;
+
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Dialog, DialogFocus, EditView, LinearLayout, TextArea, TextView},
+    Cursive,
+};
+use ize_core::{prelude::*, Deck};
+
+use crate::{file_explorer::show_file_explorer, run_options::show_run_options, utils::show_error};
+
+const NEW_DECK_ENTRY: &str = "NewDeckEntry";
+const NEW_DECK_TITLE: &str = "NewDeckTitle";
+const NEW_DECK_FRONT: &str = "NewDeckFront";
+const NEW_DECK_BACK: &str = "NewDeckBack";
+const NEW_DECK_COUNT: &str = "NewDeckCount";
+
+// The wizard rewrites the deck after every card, so backups would only hold the previous card.
+const INCREMENTAL_SAVE: SaveOptions = SaveOptions { backups: 0 };
+
+struct NewDeckState {
+    deck_path: String,
+    deck: Deck,
+}
+
+fn card_count_text(count: usize) -> String {
+    format!("Cards saved: {count}")
+}
+
+fn read_text_area(siv: &mut Cursive, name: &str) -> String {
+    siv.call_on_name(name, |view: &mut TextArea| view.get_content().to_string())
+        .expect("View not found")
+}
+
+fn clear_text_area(siv: &mut Cursive, name: &str) {
+    siv.call_on_name(name, |view: &mut TextArea| view.set_content(""))
+        .expect("View not found");
+}
+
+fn save_new_deck(siv: &mut Cursive) -> bool {
+    let result = {
+        let state = siv
+            .user_data::<NewDeckState>()
+            .expect("New deck state not found");
+        save_deck_with_options(&state.deck_path, &state.deck, &INCREMENTAL_SAVE)
+    };
+
+    if let Err(e) = result {
+        show_error(siv, &e);
+        return false;
+    }
+
+    true
+}
+
+/// Add the card in the form to the deck and write the deck out.
+fn add_card(siv: &mut Cursive) {
+    let front = read_text_area(siv, NEW_DECK_FRONT);
+    let back = read_text_area(siv, NEW_DECK_BACK);
+
+    if front.trim().is_empty() {
+        siv.add_layer(Dialog::info("A card needs a front."));
+        return;
+    }
+
+    let count = siv
+        .with_user_data(|state: &mut NewDeckState| {
+            let card_id = state.deck.new_card_id(&front);
+            state.deck.cards.insert(
+                card_id,
+                Card {
+                    card_id,
+                    front,
+                    back,
+                    direction: None,
+                    tags: vec![],
+                },
+            );
+            state.deck.cards.len()
+        })
+        .expect("New deck state not found");
+
+    if !save_new_deck(siv) {
+        return;
+    }
+
+    clear_text_area(siv, NEW_DECK_FRONT);
+    clear_text_area(siv, NEW_DECK_BACK);
+    siv.call_on_name(NEW_DECK_COUNT, |view: &mut TextView| {
+        view.set_content(card_count_text(count))
+    })
+    .expect("View not found");
+
+    // Move focus from the button back to the front so the next card can be typed straight away.
+    _ = siv.focus_name(NEW_DECK_FRONT);
+    siv.call_on_name(NEW_DECK_ENTRY, |view: &mut Dialog| {
+        view.set_focus(DialogFocus::Content)
+    })
+    .expect("View not found");
+}
+
+fn finish_new_deck(siv: &mut Cursive) {
+    let state: NewDeckState = siv.take_user_data().expect("New deck state not found");
+    siv.pop_layer();
+
+    if state.deck.cards.is_empty() {
+        return;
+    }
+
+    let deck_path = state.deck_path;
+    siv.add_layer(
+        Dialog::text(format!(
+            "Saved {} cards to {}.\nStart a run now?",
+            state.deck.cards.len(),
+            deck_path
+        ))
+        .title("Deck created")
+        .button("Main menu", |s| {
+            s.pop_layer();
+        })
+        .button("Start run", move |s| {
+            s.pop_layer();
+            show_run_options(s, &deck_path);
+        }),
+    );
+}
+
+fn show_card_entry(siv: &mut Cursive, title: &str) {
+    let layout = LinearLayout::vertical()
+        .child(TextView::new("Front"))
+        .child(TextArea::new().with_name(NEW_DECK_FRONT).min_height(4))
+        .child(TextView::new("Back"))
+        .child(TextArea::new().with_name(NEW_DECK_BACK).min_height(4))
+        .child(TextView::new(card_count_text(0)).with_name(NEW_DECK_COUNT));
+
+    siv.add_layer(
+        Dialog::around(layout.fixed_width(60))
+            .title(format!("New deck - {title}"))
+            .button("Add card", add_card)
+            .button("Done", finish_new_deck)
+            .with_name(NEW_DECK_ENTRY),
+    );
+}
+
+fn create_deck(siv: &mut Cursive, deck_path: &str) {
+    let title = siv
+        .call_on_name(NEW_DECK_TITLE, |view: &mut EditView| view.get_content())
+        .expect("View not found")
+        .trim()
+        .to_string();
+
+    let mut deck = Deck::new();
+    if !title.is_empty() {
+        deck.title = Some(title.clone());
+    }
+
+    siv.set_user_data(NewDeckState {
+        deck_path: deck_path.to_string(),
+        deck,
+    });
+
+    // Write the empty deck straight away so the location is known to be writable.
+    if !save_new_deck(siv) {
+        return;
+    }
+
+    siv.pop_layer();
+    show_card_entry(siv, if title.is_empty() { deck_path } else { &title });
+}
+
+fn show_title_prompt(siv: &mut Cursive, deck_path: &str) {
+    let deck_path = deck_path.to_string();
+
+    let layout = LinearLayout::vertical()
+        .child(TextView::new(format!("Deck file: {deck_path}")))
+        .child(TextView::new("Title"))
+        .child(EditView::new().with_name(NEW_DECK_TITLE));
+
+    siv.add_layer(
+        Dialog::around(layout.fixed_width(50))
+            .title("New deck")
+            .button("Cancel", |s| {
+                s.pop_layer();
+            })
+            .button("Next", move |s| create_deck(s, &deck_path)),
+    );
+}
+
+fn file_explorer_new_deck(siv: &mut Cursive, file_name: &str) {
+    if Path::new(file_name).exists() {
+        siv.add_layer(Dialog::info(format!(
+            "{file_name} already exists. Use Edit Deck to change it."
+        )));
+        return;
+    }
+
+    siv.pop_layer();
+    show_title_prompt(siv, file_name);
+}
+
+pub fn new_deck(siv: &mut Cursive) {
+    show_file_explorer(
+        siv,
+        "./".to_string(),
+        Box::new(file_explorer_new_deck),
+        Box::new(|s| {
+            s.pop_layer();
+        }),
+    );
+}
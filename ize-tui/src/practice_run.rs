@@ -1,4 +1,4 @@
-use std::{cmp::max, path::Path};
+use std::{cell::RefCell, cmp::max, path::Path};
 
 use cursive::{
     align::{Align, HAlign, VAlign},
@@ -12,53 +12,158 @@ use cursive::{
 use ize_core::prelude::*;
 
 use crate::{
-    file_explorer::show_file_explorer, main_menu, utils::show_error, CardContentState, RunData,
-    RunState, RUN_SELECT,
+    file_explorer::show_file_explorer,
+    main_menu,
+    run_options::AnswerMode,
+    utils::{show_error, styled_diff},
+    CardContentState, RunState,
 };
 
 const CARD_VIEW: &str = "CardView";
 const CARD_CONTENT: &str = "CardContent";
 const RUN_PROGRESS_BAR: &str = "RunProgress";
+const RUN_TIMER: &str = "RunTimer";
+const ANSWER_INPUT: &str = "AnswerInput";
+
+const BUTTONS_PER_ROW: usize = 4;
+
+#[derive(Clone, Copy)]
+enum Reshuffle {
+    All,
+    Due,
+    Session,
+    Category(RunCategory),
+}
 
 fn card_choice(siv: &mut Cursive, destination: RunCategory) {
-    siv.with_user_data(|state: &mut RunState| match destination {
-        RunCategory::Remaining => state.run_data.run.skip(),
-        _ => {
-            state
-                .run_data
-                .run
-                .move_last(RunCategory::Remaining, destination)
-                .expect("Error");
-            state.count += 1
-        }
-    });
+    let log_result = siv
+        .with_user_data(|state: &mut RunState| match destination {
+            RunCategory::Remaining => {
+                state.run_data.run.skip();
+                Ok(())
+            }
+            _ => {
+                let run = &mut state.run_data.run;
+                let record = run
+                    .remaining
+                    .last()
+                    .and_then(|id| run.review_record(*id, destination, &state.timer));
+
+                // A card answered wrongly may go back into the pile to be relearned.
+                let before = run.remaining.len();
+                run.move_last(RunCategory::Remaining, destination)
+                    .expect("Error");
+                state.count += before - run.remaining.len();
+
+                match record {
+                    Some(record) => {
+                        state.session.record(&record);
+                        run.log_review(record)
+                    }
+                    None => Ok(()),
+                }
+            }
+        })
+        .expect("Run data not found");
 
-    let count = siv.user_data::<RunState>().unwrap().count;
+    update_progress(siv);
 
-    siv.call_on_name(RUN_PROGRESS_BAR, |view: &mut ProgressBar| {
-        view.set_value(count)
-    })
-    .expect("View not found");
+    if siv.user_data::<RunState>().unwrap().session.is_over() {
+        show_session_end(siv);
+    } else {
+        show_current_card(siv);
+    }
 
-    show_current_card(siv);
+    if let Err(e) = log_result {
+        show_error(siv, &e);
+    }
 }
 
-fn reset_run(siv: &mut Cursive) {
-    let count = siv.user_data::<RunState>().unwrap().count;
-    let max = siv
+fn timer_text(session: &PracticeSession) -> String {
+    match session.time_left() {
+        Some(left) => format!("{} left", format_duration(left)),
+        None => format_duration(session.elapsed()),
+    }
+}
+
+/// Show the time spent in the session, or the time left if it has a limit.
+pub fn update_timer(siv: &mut Cursive) {
+    let Some(text) = siv
         .user_data::<RunState>()
-        .unwrap()
-        .run_data
-        .run
-        .remaining
-        .len();
+        .map(|state| timer_text(&state.session))
+    else {
+        return;
+    };
+
+    siv.call_on_name(RUN_TIMER, |view: &mut TextView| view.set_content(text));
+}
+
+/// Tell the user the session has reached its limit, and let them carry on without one.
+fn show_session_end(siv: &mut Cursive) {
+    let summary = siv.user_data::<RunState>().unwrap().session.summary();
+
+    siv.add_layer(
+        Dialog::around(TextView::new(summary.to_string()))
+            .title("Session over")
+            .button("Keep going", |s| {
+                s.pop_layer();
+                s.with_user_data(|state: &mut RunState| {
+                    state.session.limits = SessionLimits::default()
+                });
+                show_current_card(s);
+            })
+            .button("Quit", save_or_quit),
+    );
+}
+
+/// Undo the last action on the run, or redo the last undone one, and show the card it leaves on
+/// top.
+fn undo_action(siv: &mut Cursive, redo: bool) {
+    let changed = siv
+        .with_user_data(|state: &mut RunState| {
+            let run = &mut state.run_data.run;
+            let before = run.remaining.len();
+            let changed = if redo { run.redo() } else { run.undo() };
+
+            // The count is of cards taken out of the remaining pile, so undoing a grading which
+            // took one out takes back its count.
+            let after = run.remaining.len();
+            state.count = (state.count + before).saturating_sub(after);
+            changed
+        })
+        .expect("Run data not found");
+
+    if changed {
+        reset_run(siv);
+    }
+}
+
+fn reset_run(siv: &mut Cursive) {
+    update_progress(siv);
+    show_current_card(siv);
+}
+
+fn progress_label(value: usize, max: usize, relearning: usize) -> String {
+    match relearning {
+        0 => format!("{value} / {max}"),
+        _ => format!("{value} / {max} ({relearning} to relearn)"),
+    }
+}
+
+/// The progress bar counts the cards which have left the remaining pile, out of those and the
+/// cards still in it. Cards answered wrongly stay in the pile until they are relearned.
+fn update_progress(siv: &mut Cursive) {
+    let state = siv.user_data::<RunState>().unwrap();
+    let count = state.count;
+    let remaining = state.run_data.run.remaining.len();
+    let relearning = state.run_data.run.relearning_count();
+
     siv.call_on_name(RUN_PROGRESS_BAR, |view: &mut ProgressBar| {
-        view.set_max(max);
+        view.set_max(max(1, count + remaining));
         view.set_value(count);
+        view.set_label(move |value, (_, max)| progress_label(value, max, relearning));
     })
     .expect("View not found");
-
-    show_current_card(siv);
 }
 
 fn flip_card(siv: &mut Cursive) {
@@ -66,6 +171,7 @@ fn flip_card(siv: &mut Cursive) {
 
     match state {
         CardContentState::Front => {
+            siv.with_user_data(|state: &mut RunState| state.timer.flip());
             set_card_back(siv);
         }
         CardContentState::Back => {
@@ -112,35 +218,234 @@ fn set_card_front(siv: &mut Cursive) {
     .expect("View not found");
 }
 
+fn show_answer_prompt(siv: &mut Cursive) {
+    siv.add_layer(
+        Dialog::new()
+            .title("Your answer")
+            .content(
+                EditView::new()
+                    .on_submit(check_typed_answer)
+                    .with_name(ANSWER_INPUT)
+                    .fixed_width(40),
+            )
+            .button("Check", |s| {
+                let typed = s
+                    .call_on_name(ANSWER_INPUT, |view: &mut EditView| view.get_content())
+                    .expect("View not found");
+                check_typed_answer(s, &typed);
+            })
+            .button("Flip", |s| {
+                s.pop_layer();
+                flip_card(s);
+            }),
+    );
+}
+
+fn check_typed_answer(siv: &mut Cursive, typed: &str) {
+    let result = siv
+        .with_user_data(|state: &mut RunState| {
+            let run = &state.run_data.run;
+            let id = *run.remaining.last()?;
+            let expected = state.run_data.deck.item(id)?.answer();
+
+            let check = check_answer(typed, &expected, &state.answer_options);
+            let suggestion = run.suggested_category(id, check.correct);
+            Some((check, suggestion, run.category_name(suggestion).to_string()))
+        })
+        .flatten();
+
+    siv.pop_layer();
+    flip_card(siv);
+
+    let Some((check, suggestion, name)) = result else {
+        return;
+    };
+
+    // Accepting files the card, overriding leaves the choice to the buttons below the card.
+    siv.add_layer(
+        Dialog::around(TextView::new(styled_diff(&check.diff)))
+            .title(if check.correct {
+                "Correct"
+            } else {
+                "Incorrect"
+            })
+            .button(format!("Accept: {name}"), move |s| {
+                s.pop_layer();
+                card_choice(s, suggestion);
+            })
+            .button("Override", |s| {
+                s.pop_layer();
+            }),
+    );
+}
+
+fn show_choices(siv: &mut Cursive) {
+    let question = siv
+        .with_user_data(|state: &mut RunState| {
+            let data = &mut state.run_data;
+            let id = *data.run.remaining.last()?;
+            data.run.multiple_choice(&data.deck, id, DISTRACTOR_COUNT)
+        })
+        .flatten();
+
+    let Some(question) = question else {
+        return;
+    };
+
+    let correct = question.correct;
+    let mut select_view = SelectView::new();
+    for (i, choice) in question.choices.iter().enumerate() {
+        select_view.add_item(format!("{}. {}", i + 1, choice.replace('\n', " ")), i);
+    }
+    let select_view = select_view.on_submit(move |s, choice: &usize| {
+        grade_choice(s, *choice == correct);
+    });
+
+    let mut key_wrapper = OnEventView::new(select_view);
+    for i in 0..question.choices.len() {
+        if let Some(key) = char::from_digit(i as u32 + 1, 10) {
+            key_wrapper.set_on_event(key, move |s| grade_choice(s, i == correct));
+        }
+    }
+
+    siv.add_layer(
+        Dialog::around(key_wrapper)
+            .title("Choose the answer")
+            .button("Flip", |s| {
+                s.pop_layer();
+                flip_card(s);
+            }),
+    );
+}
+
+/// Reveal the back and file the card by whether the right choice was picked.
+fn grade_choice(siv: &mut Cursive, correct: bool) {
+    let result = siv
+        .with_user_data(|state: &mut RunState| {
+            let run = &state.run_data.run;
+            let id = *run.remaining.last()?;
+            let destination = run.suggested_category(id, correct);
+            Some((destination, run.category_name(destination).to_string()))
+        })
+        .flatten();
+
+    siv.pop_layer();
+    flip_card(siv);
+
+    let Some((destination, name)) = result else {
+        return;
+    };
+
+    siv.add_layer(
+        Dialog::text(format!("Moving the card to {name}."))
+            .title(if correct { "Correct" } else { "Incorrect" })
+            .button("Continue", move |s| {
+                s.pop_layer();
+                card_choice(s, destination);
+            }),
+    );
+}
+
+fn show_answer_input(siv: &mut Cursive, mode: AnswerMode) {
+    match mode {
+        AnswerMode::Flip => {}
+        AnswerMode::Typed => show_answer_prompt(siv),
+        AnswerMode::MultipleChoice => show_choices(siv),
+    }
+}
+
+/// Switch between `mode` and flipping cards.
+fn toggle_answer_mode(siv: &mut Cursive, mode: AnswerMode) {
+    let (mode, state) = siv
+        .with_user_data(|state: &mut RunState| {
+            state.answer_mode = if state.answer_mode == mode {
+                AnswerMode::Flip
+            } else {
+                mode
+            };
+            (state.answer_mode, state.card_content_state)
+        })
+        .expect("Run data not found");
+
+    if matches!(state, CardContentState::Front) {
+        show_answer_input(siv, mode);
+    }
+}
+
+/// Pick how the piles are ordered when they are next shuffled.
+fn show_order_menu(siv: &mut Cursive) {
+    let current = siv
+        .with_user_data(|state: &mut RunState| state.run_data.run.order)
+        .expect("User data failed.");
+
+    let mut select_view = SelectView::new();
+    for order in CardOrder::ALL {
+        select_view.add_item(order.description(), order);
+    }
+    if let Some(index) = CardOrder::ALL.iter().position(|order| *order == current) {
+        select_view.set_selection(index);
+    }
+
+    let select_view = select_view.on_submit(|s, order: &CardOrder| {
+        s.with_user_data(|state: &mut RunState| state.run_data.run.order = *order)
+            .expect("Run data not found");
+        s.pop_layer();
+    });
+
+    siv.add_layer(
+        Dialog::around(select_view)
+            .title("Order of cards")
+            .dismiss_button("Cancel"),
+    );
+}
+
 fn show_done_menu(siv: &mut Cursive) {
-    let (mem_count, working_count, inc_count) = siv
+    let (choices, due_count, session_count) = siv
         .with_user_data(|state: &mut RunState| {
-            (
-                state.run_data.run.memorized.len(),
-                state.run_data.run.working.len(),
-                state.run_data.run.incorrect.len(),
-            )
+            let run = &state.run_data.run;
+            let choices: Vec<(String, usize, RunCategory)> = run
+                .box_categories()
+                .map(|category| {
+                    (
+                        run.category_name(category).to_string(),
+                        run.category(category).map_or(0, |ids| ids.len()),
+                        category,
+                    )
+                })
+                .collect();
+
+            (choices, run.due_count(today()), run.next_session_count())
         })
         .expect("User data failed.");
-    let all_count = mem_count + working_count + inc_count;
+    let all_count: usize = choices.iter().map(|(_, count, _)| count).sum();
 
     let mut select_view = SelectView::new();
-    select_view.add_item(format!("All ({})", all_count), RunCategory::Remaining);
-    if inc_count > 0 {
-        select_view.add_item(format!("Incorrect ({})", inc_count), RunCategory::Incorrect);
+    if due_count > 0 {
+        select_view.add_item(format!("Due today ({})", due_count), Reshuffle::Due);
     }
-    if working_count > 0 {
-        select_view.add_item(format!("Working ({})", working_count), RunCategory::Working);
+    if session_count > 0 {
+        select_view.add_item(
+            format!("Next session ({})", session_count),
+            Reshuffle::Session,
+        );
     }
-    if mem_count > 0 {
-        select_view.add_item(format!("Memorized ({})", mem_count), RunCategory::Memorized);
+    select_view.add_item(format!("All ({})", all_count), Reshuffle::All);
+    for (name, count, category) in choices {
+        if count > 0 {
+            select_view.add_item(
+                format!("{} ({})", name, count),
+                Reshuffle::Category(category),
+            );
+        }
     }
 
-    let select_view = select_view.on_submit(|s, run_category| {
+    let select_view = select_view.on_submit(|s, reshuffle| {
         s.with_user_data(|state: &mut RunState| {
-            match run_category {
-                RunCategory::Remaining => state.run_data.run.reset(),
-                _ => {
+            match reshuffle {
+                Reshuffle::All => state.run_data.run.reset(),
+                Reshuffle::Due => state.run_data.run.queue_due(today()),
+                Reshuffle::Session => state.run_data.run.start_session(),
+                Reshuffle::Category(run_category) => {
                     state
                         .run_data
                         .run
@@ -160,6 +465,13 @@ fn show_done_menu(siv: &mut Cursive) {
         Dialog::new()
             .title("Which pile to reshuffle?")
             .content(select_view)
+            .button("Order", show_order_menu)
+            .button("Undo", |s| {
+                if s.user_data::<RunState>().unwrap().run_data.run.can_undo() {
+                    s.pop_layer();
+                    undo_action(s, false);
+                }
+            })
             .button("Quit", |s| {
                 save_or_quit(s);
             }),
@@ -178,12 +490,14 @@ fn save_and_quit(siv: &mut Cursive) {
         .to_str()
         .unwrap()
         .to_string();
+    // Saving notes where the run went, while the file explorer only lends the action.
+    let run_state = RefCell::new(run_state);
 
     show_file_explorer(
         siv,
         base_path,
         Box::new(move |s, file_path| {
-            let result = save_practice_run(file_path, &run_state.run_data.run);
+            let result = save_practice_run(file_path, &mut run_state.borrow_mut().run_data.run);
 
             match result {
                 Ok(_) => {
@@ -191,7 +505,7 @@ fn save_and_quit(siv: &mut Cursive) {
                     main_menu(s);
                 }
                 Err(e) => {
-                    show_error(s, e.as_ref());
+                    show_error(s, &e);
                 }
             }
         }),
@@ -203,9 +517,11 @@ fn save_and_quit(siv: &mut Cursive) {
 }
 
 fn save_or_quit(siv: &mut Cursive) {
+    let summary = siv.user_data::<RunState>().unwrap().session.summary();
+
     siv.pop_layer();
     siv.add_layer(
-        Dialog::new()
+        Dialog::around(TextView::new(summary.to_string()))
             .title("Save this run?")
             .button("Save", |s| {
                 s.pop_layer();
@@ -231,7 +547,14 @@ fn show_current_card(siv: &mut Cursive) {
         return;
     }
 
+    let mode = siv
+        .with_user_data(|state: &mut RunState| {
+            state.timer = ReviewTimer::start();
+            state.answer_mode
+        })
+        .expect("Run data not found");
     set_card_front(siv);
+    show_answer_input(siv, mode);
 }
 
 // Assume that user data has been set
@@ -251,23 +574,34 @@ pub fn begin_run(siv: &mut Cursive) {
         .with_name(CARD_VIEW)
         .fixed_height(15);
 
-    let bottom_menu = LinearLayout::horizontal()
-        .child(Button::new("1.Skip", |s| {
-            card_choice(s, RunCategory::Remaining);
-        }))
-        .child(Button::new("2.Incorrect", |s| {
-            card_choice(s, RunCategory::Incorrect);
-        }))
-        .child(Button::new("3.Working", |s| {
-            card_choice(s, RunCategory::Working);
-        }))
-        .child(Button::new("4.Memorized", |s| {
-            card_choice(s, RunCategory::Memorized);
-        }))
-        .child(DummyView)
-        .child(Button::new("Quit", |s| {
-            save_or_quit(s);
+    let choices: Vec<(String, RunCategory)> = {
+        let run = &siv.user_data::<RunState>().unwrap().run_data.run;
+        std::iter::once(("Skip".to_string(), RunCategory::Remaining))
+            .chain(
+                run.box_categories()
+                    .map(|category| (run.category_name(category).to_string(), category)),
+            )
+            .collect()
+    };
+
+    let mut bottom_menu = LinearLayout::vertical();
+    let mut button_row = LinearLayout::horizontal();
+    for (i, (name, category)) in choices.iter().enumerate() {
+        if i > 0 && i % BUTTONS_PER_ROW == 0 {
+            bottom_menu.add_child(button_row);
+            button_row = LinearLayout::horizontal();
+        }
+
+        let category = *category;
+        button_row.add_child(Button::new(format!("{}.{}", i + 1, name), move |s| {
+            card_choice(s, category);
         }));
+    }
+    button_row.add_child(DummyView);
+    button_row.add_child(Button::new("Quit", |s| {
+        save_or_quit(s);
+    }));
+    bottom_menu.add_child(button_row);
 
     let n_cards = siv
         .user_data::<RunState>()
@@ -282,26 +616,38 @@ pub fn begin_run(siv: &mut Cursive) {
     let progress = ProgressBar::new()
         .min(0)
         .max(max_cards)
-        .with_label(|value, (_, max)| format!("{value} / {max}"))
-        .with_name(RUN_PROGRESS_BAR);
+        .with_label(|value, (_, max)| progress_label(value, max, 0))
+        .with_name(RUN_PROGRESS_BAR)
+        .full_width();
+    let timer = TextView::new(timer_text(&siv.user_data::<RunState>().unwrap().session))
+        .h_align(HAlign::Right)
+        .with_name(RUN_TIMER)
+        .fixed_width(12);
 
     let run_screen = LinearLayout::vertical()
         .child(card_view)
-        .child(progress)
+        .child(LinearLayout::horizontal().child(progress).child(timer))
         .child(bottom_menu)
         .fixed_width(55);
 
     let key_wrapper = OnEventView::new(run_screen)
-        .on_event(' ', |s| flip_card(s))
-        .on_event('0', |s| flip_card(s))
-        .on_event('1', |s| card_choice(s, RunCategory::Remaining))
-        .on_event('2', |s| card_choice(s, RunCategory::Incorrect))
-        .on_event('3', |s| card_choice(s, RunCategory::Working))
-        .on_event('4', |s| card_choice(s, RunCategory::Memorized))
+        .on_event(' ', flip_card)
+        .on_event('0', flip_card)
+        .on_event('t', |s| toggle_answer_mode(s, AnswerMode::Typed))
+        .on_event('m', |s| toggle_answer_mode(s, AnswerMode::MultipleChoice))
+        .on_event('u', |s| undo_action(s, false))
+        .on_event('r', |s| undo_action(s, true))
         .on_event('q', |s| {
             main_menu(s);
         });
 
+    let mut key_wrapper = key_wrapper;
+    for (i, (_, category)) in choices.into_iter().enumerate() {
+        if let Some(key) = char::from_digit(i as u32 + 1, 10) {
+            key_wrapper.set_on_event(key, move |s| card_choice(s, category));
+        }
+    }
+
     siv.add_layer(key_wrapper);
     show_current_card(siv);
 }
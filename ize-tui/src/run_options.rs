@@ -0,0 +1,331 @@
+use std::{cell::RefCell, path::Path, rc::Rc, sync::OnceLock, time::Duration};
+
+use cursive::{
+    view::{Nameable, Resizable},
+    views::{Button, Checkbox, Dialog, EditView, LinearLayout, SelectView, TextView},
+    Cursive,
+};
+use ize_core::{anki::is_anki_package, prelude::*};
+
+use crate::{file_explorer::show_file_explorer, file_explorer_load_deck_new_run};
+
+const RUN_OPTIONS_ALGORITHM: &str = "RunOptionsAlgorithm";
+const RUN_OPTIONS_LAYOUT: &str = "RunOptionsLayout";
+const RUN_OPTIONS_ANKI_STATE: &str = "RunOptionsAnkiState";
+const RUN_OPTIONS_FILTER: &str = "RunOptionsFilter";
+const RUN_OPTIONS_DECKS: &str = "RunOptionsDecks";
+const RUN_OPTIONS_ANSWER_MODE: &str = "RunOptionsAnswerMode";
+const RUN_OPTIONS_IGNORE_CASE: &str = "RunOptionsIgnoreCase";
+const RUN_OPTIONS_IGNORE_WHITESPACE: &str = "RunOptionsIgnoreWhitespace";
+const RUN_OPTIONS_IGNORE_PUNCTUATION: &str = "RunOptionsIgnorePunctuation";
+const RUN_OPTIONS_TIME_LIMIT: &str = "RunOptionsTimeLimit";
+const RUN_OPTIONS_MAX_CARDS: &str = "RunOptionsMaxCards";
+const RUN_OPTIONS_LEARNING_STEPS: &str = "RunOptionsLearningSteps";
+const RUN_OPTIONS_ORDER: &str = "RunOptionsOrder";
+const RUN_OPTIONS_SEED: &str = "RunOptionsSeed";
+
+/// The seed given with `--seed`, which new runs use unless another is entered and resumed runs
+/// shuffle from.
+pub static DEFAULT_SEED: OnceLock<u64> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AnswerMode {
+    /// Flip the card and pick its box.
+    Flip,
+    /// Type the answer and have it checked.
+    Typed,
+    /// Pick the answer out of a few from the deck.
+    MultipleChoice,
+}
+
+pub struct RunOptions {
+    pub algorithm: Algorithm,
+    pub layout: BoxLayout,
+    /// Only study the cards with these tags.
+    pub filter: TagFilter,
+    /// Start from the review progress stored in an Anki package.
+    pub import_anki_state: bool,
+    pub answer_mode: AnswerMode,
+    pub answer_options: AnswerOptions,
+    pub limits: SessionLimits,
+    /// After how many cards a card answered wrongly comes back.
+    pub learning_steps: Vec<usize>,
+    pub order: CardOrder,
+    /// Shuffle as every other run with this seed, or at random if there is none.
+    pub seed: Option<u64>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            algorithm: Algorithm::Sm2,
+            layout: BoxLayout::Piles,
+            filter: TagFilter::default(),
+            import_anki_state: false,
+            answer_mode: AnswerMode::Flip,
+            answer_options: AnswerOptions::default(),
+            limits: SessionLimits::default(),
+            learning_steps: DEFAULT_LEARNING_STEPS.to_vec(),
+            order: CardOrder::default(),
+            seed: DEFAULT_SEED.get().copied(),
+        }
+    }
+}
+
+fn read_selection<T: Copy + 'static>(siv: &mut Cursive, name: &str) -> Option<T> {
+    siv.call_on_name(name, |view: &mut SelectView<T>| view.selection())
+        .expect("View not found")
+        .map(|selection| *selection)
+}
+
+fn read_checkbox(siv: &mut Cursive, name: &str) -> Option<bool> {
+    siv.call_on_name(name, |view: &mut Checkbox| view.is_checked())
+}
+
+fn named_checkbox(name: &str, label: &str, checked: bool) -> LinearLayout {
+    LinearLayout::horizontal()
+        .child(Checkbox::new().with_checked(checked).with_name(name))
+        .child(TextView::new(format!(" {label} ")))
+}
+
+fn option_row(label: &str, view: impl cursive::View) -> LinearLayout {
+    LinearLayout::horizontal()
+        .child(TextView::new(label).fixed_width(12))
+        .child(view)
+}
+
+/// The number in the edit view, or `None` if it is empty.
+fn read_number(siv: &mut Cursive, name: &str, label: &str) -> Result<Option<u64>, String> {
+    let text = siv
+        .call_on_name(name, |view: &mut EditView| view.get_content())
+        .expect("View not found");
+
+    match text.trim() {
+        "" => Ok(None),
+        number => number
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("{label} '{number}' must be a whole number.")),
+    }
+}
+
+fn read_run_options(siv: &mut Cursive) -> Result<RunOptions, String> {
+    let defaults = RunOptions::default();
+
+    let filter = siv
+        .call_on_name(RUN_OPTIONS_FILTER, |view: &mut EditView| view.get_content())
+        .expect("View not found")
+        .parse()?;
+
+    let learning_steps = siv
+        .call_on_name(RUN_OPTIONS_LEARNING_STEPS, |view: &mut EditView| {
+            view.get_content()
+        })
+        .expect("View not found");
+    let learning_steps = parse_learning_steps(&learning_steps)?;
+
+    let limits = SessionLimits {
+        time_limit: read_number(siv, RUN_OPTIONS_TIME_LIMIT, "Time limit")?
+            .map(|minutes| Duration::from_secs(minutes * 60)),
+        max_cards: read_number(siv, RUN_OPTIONS_MAX_CARDS, "Max cards")?
+            .map(|count| count as usize),
+    };
+
+    Ok(RunOptions {
+        algorithm: read_selection(siv, RUN_OPTIONS_ALGORITHM).unwrap_or(defaults.algorithm),
+        layout: read_selection(siv, RUN_OPTIONS_LAYOUT).unwrap_or(defaults.layout),
+        filter,
+        import_anki_state: read_checkbox(siv, RUN_OPTIONS_ANKI_STATE)
+            .unwrap_or(defaults.import_anki_state),
+        answer_mode: read_selection(siv, RUN_OPTIONS_ANSWER_MODE).unwrap_or(defaults.answer_mode),
+        answer_options: AnswerOptions {
+            ignore_case: read_checkbox(siv, RUN_OPTIONS_IGNORE_CASE)
+                .unwrap_or(defaults.answer_options.ignore_case),
+            ignore_whitespace: read_checkbox(siv, RUN_OPTIONS_IGNORE_WHITESPACE)
+                .unwrap_or(defaults.answer_options.ignore_whitespace),
+            ignore_punctuation: read_checkbox(siv, RUN_OPTIONS_IGNORE_PUNCTUATION)
+                .unwrap_or(defaults.answer_options.ignore_punctuation),
+        },
+        limits,
+        learning_steps,
+        order: read_selection(siv, RUN_OPTIONS_ORDER).unwrap_or(defaults.order),
+        seed: read_number(siv, RUN_OPTIONS_SEED, "Seed")?,
+    })
+}
+
+fn deck_names(deck_paths: &[String]) -> String {
+    let names: Vec<String> = deck_paths
+        .iter()
+        .map(|path| {
+            Path::new(path)
+                .file_name()
+                .map_or(path.clone(), |name| name.to_string_lossy().to_string())
+        })
+        .collect();
+
+    names.join(", ")
+}
+
+/// Let the user pick another deck to study in the same run.
+fn add_deck(siv: &mut Cursive, deck_paths: Rc<RefCell<Vec<String>>>) {
+    show_file_explorer(
+        siv,
+        "./".to_string(),
+        Box::new(move |s, deck_path| {
+            s.pop_layer();
+
+            let mut paths = deck_paths.borrow_mut();
+            if !paths.iter().any(|path| path == deck_path) {
+                paths.push(deck_path.to_string());
+            }
+
+            let names = deck_names(&paths);
+            s.call_on_name(RUN_OPTIONS_DECKS, |view: &mut TextView| {
+                view.set_content(names)
+            });
+        }),
+        Box::new(|s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+pub fn show_run_options(siv: &mut Cursive, deck_path: &str) {
+    let deck_paths = Rc::new(RefCell::new(vec![deck_path.to_string()]));
+
+    let mut algorithms = SelectView::new().popup();
+    for algorithm in Algorithm::ALL {
+        algorithms.add_item(algorithm.name(), algorithm);
+    }
+
+    let mut layouts = SelectView::new().popup();
+    for layout in BoxLayout::ALL {
+        layouts.add_item(layout.name(), layout);
+    }
+
+    let mut orders = SelectView::new().popup();
+    for order in CardOrder::ALL {
+        orders.add_item(order.description(), order);
+    }
+
+    let answers = SelectView::new()
+        .popup()
+        .item("Flip card", AnswerMode::Flip)
+        .item("Type answer", AnswerMode::Typed)
+        .item("Multiple choice", AnswerMode::MultipleChoice);
+
+    let answer_defaults = AnswerOptions::default();
+    let ignored = LinearLayout::horizontal()
+        .child(named_checkbox(
+            RUN_OPTIONS_IGNORE_CASE,
+            "case",
+            answer_defaults.ignore_case,
+        ))
+        .child(named_checkbox(
+            RUN_OPTIONS_IGNORE_WHITESPACE,
+            "spaces",
+            answer_defaults.ignore_whitespace,
+        ))
+        .child(named_checkbox(
+            RUN_OPTIONS_IGNORE_PUNCTUATION,
+            "punctuation",
+            answer_defaults.ignore_punctuation,
+        ));
+
+    let add_paths = deck_paths.clone();
+    let decks = LinearLayout::vertical()
+        .child(TextView::new(deck_names(&deck_paths.borrow())).with_name(RUN_OPTIONS_DECKS))
+        .child(Button::new("Add deck", move |s| {
+            add_deck(s, add_paths.clone())
+        }));
+
+    let mut layout = LinearLayout::vertical()
+        .child(option_row("Decks:", decks))
+        .child(option_row(
+            "Scheduler:",
+            algorithms.with_name(RUN_OPTIONS_ALGORITHM),
+        ))
+        .child(option_row("Boxes:", layouts.with_name(RUN_OPTIONS_LAYOUT)))
+        .child(option_row("Order:", orders.with_name(RUN_OPTIONS_ORDER)))
+        .child(option_row(
+            "Seed:",
+            LinearLayout::horizontal()
+                .child(
+                    EditView::new()
+                        .content(
+                            DEFAULT_SEED
+                                .get()
+                                .map_or(String::new(), |seed| seed.to_string()),
+                        )
+                        .with_name(RUN_OPTIONS_SEED)
+                        .fixed_width(22),
+                )
+                .child(TextView::new(" or empty for any")),
+        ))
+        .child(option_row(
+            "Tags:",
+            EditView::new().with_name(RUN_OPTIONS_FILTER).full_width(),
+        ))
+        .child(option_row(
+            "",
+            TextView::new("e.g. verbs AND irregular NOT draft"),
+        ))
+        .child(option_row(
+            "Answers:",
+            answers.with_name(RUN_OPTIONS_ANSWER_MODE),
+        ))
+        .child(option_row("Ignore:", ignored))
+        .child(option_row(
+            "Relearn:",
+            LinearLayout::horizontal()
+                .child(
+                    EditView::new()
+                        .content(format_learning_steps(&DEFAULT_LEARNING_STEPS))
+                        .with_name(RUN_OPTIONS_LEARNING_STEPS)
+                        .fixed_width(10),
+                )
+                .child(TextView::new(" cards later, or none")),
+        ))
+        .child(option_row(
+            "Time limit:",
+            LinearLayout::horizontal()
+                .child(
+                    EditView::new()
+                        .with_name(RUN_OPTIONS_TIME_LIMIT)
+                        .fixed_width(6),
+                )
+                .child(TextView::new(" minutes")),
+        ))
+        .child(option_row(
+            "Max cards:",
+            EditView::new()
+                .with_name(RUN_OPTIONS_MAX_CARDS)
+                .fixed_width(6),
+        ));
+
+    if is_anki_package(deck_path) {
+        layout.add_child(option_row(
+            "Anki state:",
+            Checkbox::new().checked().with_name(RUN_OPTIONS_ANKI_STATE),
+        ));
+    }
+
+    siv.add_layer(
+        Dialog::around(layout.fixed_width(50))
+            .title("Run options")
+            .button("Cancel", |s| {
+                s.pop_layer();
+            })
+            .button("Start", move |s| {
+                let options = match read_run_options(s) {
+                    Ok(options) => options,
+                    Err(e) => {
+                        s.add_layer(Dialog::info(e));
+                        return;
+                    }
+                };
+                s.pop_layer();
+                file_explorer_load_deck_new_run(s, &deck_paths.borrow(), &options);
+            }),
+    );
+}
@@ -1,17 +1,84 @@
 use std::error::Error;
 
 use cursive::{
+    theme::{BaseColor, Effect, Style},
+    utils::markup::StyledString,
     views::{Dialog, TextView},
     Cursive,
 };
+use ize_core::prelude::*;
 
-pub fn show_error(siv: &mut Cursive, err: &dyn Error) {
-    siv.add_layer(
-        Dialog::new()
-            .title("Error!")
-            .content(TextView::new(err.to_string()))
-            .button("Ok", |s| {
+use crate::locate_deck;
+
+fn error_title(err: &IzeError) -> &'static str {
+    match err {
+        IzeError::Io { .. } => "File Error",
+        IzeError::Parse { .. } => "Invalid File",
+        IzeError::Format { .. } => "Unsupported File",
+        IzeError::MissingDeck { .. } => "Deck Not Found",
+        IzeError::RunInconsistent { .. } => "Invalid Run",
+        IzeError::Save(_) => "Save Failed",
+    }
+}
+
+pub fn show_error(siv: &mut Cursive, err: &(dyn Error + 'static)) {
+    let mut dialog = Dialog::new()
+        .title("Error!")
+        .content(TextView::new(err.to_string()))
+        .button("Ok", |s| {
+            s.pop_layer();
+        });
+
+    if let Some(err) = err.downcast_ref::<IzeError>() {
+        dialog.set_title(error_title(err));
+
+        // Let the user point the run at the deck's new location.
+        if let IzeError::MissingDeck { run_path, .. } = err {
+            let run_path = run_path.to_string_lossy().to_string();
+            dialog.add_button("Locate Deck", move |s| {
                 s.pop_layer();
-            }),
-    )
+                locate_deck(s, &run_path);
+            });
+        }
+    }
+
+    siv.add_layer(dialog)
+}
+
+/// Card text with cloze blanks in bold and revealed answers highlighted.
+pub fn styled_spans(spans: &[Span]) -> StyledString {
+    let mut styled = StyledString::new();
+    for span in spans {
+        match span {
+            Span::Text(text) => styled.append_plain(text),
+            Span::Blank(text) => styled.append_styled(text, Effect::Bold),
+            Span::Answer(text) => styled.append_styled(
+                text,
+                Style::from(BaseColor::Blue.dark()).combine(Effect::Bold),
+            ),
+        }
+    }
+
+    styled
+}
+
+/// A typed answer compared with the expected one: missing characters in green and extra ones
+/// struck through in red.
+pub fn styled_diff(diff: &[Diff]) -> StyledString {
+    let mut styled = StyledString::new();
+    for part in diff {
+        match part {
+            Diff::Same(text) => styled.append_plain(text),
+            Diff::Missing(text) => styled.append_styled(
+                text,
+                Style::from(BaseColor::Green.dark()).combine(Effect::Bold),
+            ),
+            Diff::Extra(text) => styled.append_styled(
+                text,
+                Style::from(BaseColor::Red.dark()).combine(Effect::Strikethrough),
+            ),
+        }
+    }
+
+    styled
 }